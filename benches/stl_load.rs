@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use solidify::stl::load_stl;
+
+const NUM_TRIANGLES: u32 = 500_000;
+
+// A binary STL of small random-ish triangles, written once per run
+fn write_test_stl() -> PathBuf {
+    let path = std::env::temp_dir().join("solidify_bench.stl");
+    let mut file = BufWriter::new(File::create(&path).unwrap());
+    file.write_all(&[0u8; 80]).unwrap();
+    file.write_all(&NUM_TRIANGLES.to_le_bytes()).unwrap();
+    let mut seed = 1u32;
+    for _ in 0..NUM_TRIANGLES {
+        for _ in 0..12 {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            file.write_all(&((seed >> 8) as f32 / 65536.0).to_le_bytes()).unwrap();
+        }
+        file.write_all(&0u16.to_le_bytes()).unwrap();
+    }
+    file.flush().unwrap();
+    path
+}
+
+// What load_stl used to do, one read call and three pushes per facet
+fn load_per_triangle(path: &Path) -> (Vec<[f32; 3]>, Vec<[u32; 3]>) {
+    let mut file = File::open(path).unwrap();
+    let mut header = [0u8; 84];
+    file.read_exact(&mut header).unwrap();
+    let num_tris = u32::from_le_bytes(header[80..84].try_into().unwrap());
+
+    let mut positions = Vec::new();
+    let mut triangles = Vec::new();
+    let mut tri_buffer = [0u8; 50];
+    for _ in 0..num_tris {
+        file.read_exact(&mut tri_buffer).unwrap();
+        let read_vec3 = |offset: usize| [0, 1, 2]
+            .map(|i| f32::from_le_bytes(tri_buffer[offset + i * 4..][..4].try_into().unwrap()));
+        let current_index = positions.len() as u32;
+        positions.extend_from_slice(&[read_vec3(12), read_vec3(24), read_vec3(36)]);
+        triangles.push([current_index, current_index + 1, current_index + 2]);
+    }
+    (positions, triangles)
+}
+
+fn bench_stl_load(c: &mut Criterion) {
+    let path = write_test_stl();
+    let mut group = c.benchmark_group("binary stl");
+    group.throughput(Throughput::Bytes(std::fs::metadata(&path).unwrap().len()));
+    group.sample_size(10);
+    group.bench_function("per triangle reads", |b| b.iter(|| load_per_triangle(&path)));
+    group.bench_function("memory mapped", |b| b.iter(|| load_stl(&path).unwrap()));
+    group.finish();
+    std::fs::remove_file(path).ok();
+}
+
+criterion_group!(benches, bench_stl_load);
+criterion_main!(benches);
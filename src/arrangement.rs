@@ -0,0 +1,605 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use rayon::prelude::*;
+
+use crate::broadphase::overlapping_pairs;
+use crate::exact::{
+    clip_segment, cross, dot, line_intersection, normal, orient2d, orient2d_f32, projection_axes, segment_plane,
+    side_of_plane, Exact, ExactPoint,
+};
+use crate::mesh::{facet_normal, TriMesh};
+
+// A mesh with every face split along the curves where it meets other
+// faces, so that no two faces cross anymore and faces that did now share
+// the vertices and edges along those curves. Points are exact, the first
+// ones being the original vertices. Faces keep the winding of the face
+// they came from.
+pub struct Arrangement {
+    pub points: Vec<ExactPoint>,
+    pub triangles: Vec<[u32; 3]>,
+    // Original face of every face
+    pub source: Vec<u32>,
+    // Edges lying along an intersection, as sorted vertex pairs
+    pub seams: HashSet<[u32; 2]>,
+    // Original faces lying in the same plane with overlapping interiors
+    pub coplanar: Vec<[u32; 2]>,
+    pub intersecting_pairs: usize,
+    pub split_faces: usize,
+}
+
+// Pieces of intersection lying in one face, by point index
+#[derive(Default)]
+struct Constraints {
+    points: Vec<u32>,
+    segments: Vec<[u32; 2]>,
+}
+
+// The triangles a face is split into and the edges of theirs lying along
+// intersections
+type SplitFace = (Vec<[u32; 3]>, Vec<[u32; 2]>);
+
+// Where two faces meet, as seen from either of them. Each piece is a point
+// or a segment. Faces crossing at an angle see the same single piece.
+struct Contact {
+    faces: [u32; 2],
+    pieces: [Vec<Vec<ExactPoint>>; 2],
+    coplanar: bool,
+}
+
+// Splits the faces of a welded mesh wherever they cross each other
+pub fn arrange(mesh: &TriMesh) -> Arrangement {
+    let mut points: Vec<ExactPoint> = mesh.positions.iter().map(|&position| ExactPoint::from_f32(position)).collect();
+    let mut lookup: HashMap<Exact, u32> = points.iter()
+        .enumerate()
+        .map(|(index, point)| (point.exact.clone(), index as u32))
+        .collect();
+
+    let contacts: Vec<Contact> = overlapping_pairs(mesh).into_par_iter()
+        .filter_map(|faces| contact(mesh, faces))
+        .collect();
+
+    let mut constraints: BTreeMap<u32, Constraints> = BTreeMap::new();
+    let mut coplanar = Vec::new();
+    for contact in &contacts {
+        for (face, pieces) in contact.faces.iter().zip(&contact.pieces) {
+            let constraints = constraints.entry(*face).or_default();
+            for piece in pieces {
+                let ends: Vec<u32> = piece.iter().map(|point| intern(&mut points, &mut lookup, point)).collect();
+                match ends[..] {
+                    [start, end] if start != end => constraints.segments.push([start, end]),
+                    _ => constraints.points.push(ends[0]),
+                }
+            }
+        }
+        if contact.coplanar {
+            coplanar.push(contact.faces);
+        }
+    }
+
+    // Where the curves through a face cross each other, which is also where
+    // they cross in the other faces involved
+    let crossings: Vec<(u32, Vec<ExactPoint>)> = constraints.par_iter()
+        .map(|(&face, constraints)| (face, segment_crossings(&points, mesh.triangles[face as usize], &constraints.segments)))
+        .collect();
+    for (face, crossings) in crossings {
+        let constraints = constraints.get_mut(&face).unwrap();
+        for point in &crossings {
+            constraints.points.push(intern(&mut points, &mut lookup, point));
+        }
+    }
+
+    let mut split: HashMap<u32, SplitFace> = constraints.par_iter()
+        .map(|(&face, constraints)| (face, split_face(&points, mesh.triangles[face as usize], constraints)))
+        .collect();
+
+    let mut triangles = Vec::with_capacity(mesh.num_triangles());
+    let mut source = Vec::with_capacity(mesh.num_triangles());
+    let mut seams = HashSet::new();
+    for (face, &tri) in mesh.triangles.iter().enumerate() {
+        match split.remove(&(face as u32)) {
+            Some((pieces, edges)) => {
+                source.extend(std::iter::repeat_n(face as u32, pieces.len()));
+                triangles.extend(pieces);
+                seams.extend(edges.into_iter().map(|[a, b]| [a.min(b), a.max(b)]));
+            },
+            None => {
+                triangles.push(tri);
+                source.push(face as u32);
+            },
+        }
+    }
+
+    Arrangement {
+        points,
+        triangles,
+        source,
+        seams,
+        coplanar,
+        intersecting_pairs: contacts.len(),
+        split_faces: constraints.len(),
+    }
+}
+
+impl Arrangement {
+    // The faces picked out by keep as a mesh, carrying over the colors of
+    // the faces and vertices they came from. Points no kept face uses are
+    // left out. Positions are rounded back to f32, so faces meeting where
+    // three surfaces cross can end up overlapping by a rounding error.
+    pub fn to_mesh(&self, original: &TriMesh, keep: &[bool]) -> TriMesh {
+        let mut mesh = TriMesh::new(&original.name);
+        let mut colors = original.colors.as_ref().map(|_| Vec::new());
+        let mut face_colors = original.face_colors.as_ref().map(|_| Vec::new());
+        let mut compact: Vec<Option<u32>> = vec![None; self.points.len()];
+        for (face, tri) in self.triangles.iter().enumerate().filter(|(face, _)| keep[*face]) {
+            let source = self.source[face] as usize;
+            let tri = tri.map(|index| {
+                *compact[index as usize].get_or_insert_with(|| {
+                    mesh.positions.push(self.points[index as usize].to_f32());
+                    if let (Some(colors), Some(source_colors)) = (colors.as_mut(), original.colors.as_ref()) {
+                        // New points take the color of a corner of the face they were cut into
+                        let vertex = if (index as usize) < original.positions.len() { index } else { original.triangles[source][0] };
+                        colors.push(source_colors[vertex as usize]);
+                    }
+                    mesh.positions.len() as u32 - 1
+                })
+            });
+            mesh.triangles.push(tri);
+            if let (Some(face_colors), Some(source_colors)) = (face_colors.as_mut(), original.face_colors.as_ref()) {
+                face_colors.push(source_colors[source]);
+            }
+        }
+
+        mesh.colors = colors;
+        mesh.face_colors = face_colors;
+        mesh
+    }
+}
+
+fn intern(points: &mut Vec<ExactPoint>, lookup: &mut HashMap<Exact, u32>, point: &ExactPoint) -> u32 {
+    *lookup.entry(point.exact.clone()).or_insert_with(|| {
+        points.push(point.clone());
+        points.len() as u32 - 1
+    })
+}
+
+fn contact(mesh: &TriMesh, [f, g]: [u32; 2]) -> Option<Contact> {
+    let (tri_f, tri_g) = (mesh.triangles[f as usize], mesh.triangles[g as usize]);
+    let shared = tri_f.iter().filter(|vertex| tri_g.contains(vertex)).count();
+    // Neighbours across an edge only meet along it, unless folded onto each other
+    if shared > 1 {
+        return None;
+    }
+
+    let (a, b) = (mesh.triangle(f as usize), mesh.triangle(g as usize));
+    let sides_a = a.map(|point| side_of_plane(b, point));
+    let sides_b = b.map(|point| side_of_plane(a, point));
+    if clear_of_plane(&sides_a, tri_f, tri_g) || clear_of_plane(&sides_b, tri_g, tri_f) {
+        return None;
+    }
+    if sides_a.iter().all(|side| side.is_eq()) {
+        return coplanar_contact([f, g], a, b);
+    }
+    if apart_along_line(a, b, &sides_a, &sides_b) {
+        return None;
+    }
+
+    // Both faces cross the line where their planes meet, and meet each
+    // other where the two stretches of it overlap
+    let direction = cross(&normal(&a.map(ExactPoint::from_f32)), &normal(&b.map(ExactPoint::from_f32)));
+    let along = |point: &ExactPoint| dot(&point.exact, &direction);
+    let stretch = |tri: [[f32; 3]; 3], sides: [Ordering; 3], plane: [[f32; 3]; 3]| {
+        let mut points = plane_section(tri, sides, plane);
+        points.sort_by_cached_key(along);
+        let last = points.pop().unwrap();
+        let first = points.into_iter().next().unwrap_or_else(|| last.clone());
+        (first, last)
+    };
+    let (start_a, end_a) = stretch(a, sides_a, b);
+    let (start_b, end_b) = stretch(b, sides_b, a);
+    let start = if along(&start_a) >= along(&start_b) { start_a } else { start_b };
+    let end = if along(&end_a) <= along(&end_b) { end_a } else { end_b };
+    let piece = match along(&start).cmp(&along(&end)) {
+        Ordering::Greater => return None,
+        // Faces sharing a corner always touch there, which needs no splitting
+        Ordering::Equal if shared > 0 => return None,
+        Ordering::Equal => vec![start],
+        Ordering::Less => vec![start, end],
+    };
+    Some(Contact { faces: [f, g], pieces: [vec![piece.clone()], vec![piece]], coplanar: false })
+}
+
+// Whether the corners a face doesn't share with the other are all strictly
+// on one side of the other's plane, so they can only meet at shared corners
+fn clear_of_plane(sides: &[Ordering; 3], tri: [u32; 3], other: [u32; 3]) -> bool {
+    let mut own = (0..3).filter(|&i| !other.contains(&tri[i])).map(|i| sides[i]);
+    let first = own.next();
+    first.is_some_and(|first| first.is_ne() && own.all(|side| side == first))
+}
+
+// Whether two faces with every corner strictly off the other's plane cross
+// the line their planes meet on in separate stretches. Each stretch runs
+// between where the edges from the corner alone on its side cross the other
+// plane, and which way round two of those lie along the line shows in the
+// orientation of the two edges. The stretches are apart exactly when all
+// four pairings agree, which settles most near misses on the input alone.
+fn apart_along_line(a: [[f32; 3]; 3], b: [[f32; 3]; 3], sides_a: &[Ordering; 3], sides_b: &[Ordering; 3]) -> bool {
+    if sides_a.iter().chain(sides_b).any(|side| side.is_eq()) {
+        return false;
+    }
+    // Past the plane test, each face has one corner alone on its side
+    let lone = |sides: &[Ordering; 3]| (0..3).find(|&i| sides[(i + 1) % 3] == sides[(i + 2) % 3]).unwrap();
+    let (i, j) = (lone(sides_a), lone(sides_b));
+
+    let coord = |p: [f32; 3]| robust::Coord3D { x: p[0], y: p[1], z: p[2] };
+    let order = |e: usize, f: usize| {
+        let (p, q, r, s) = (a[i], a[(i + e) % 3], b[j], b[(j + f) % 3]);
+        robust::orient3d(coord(p), coord(q), coord(r), coord(s)).partial_cmp(&0.0).unwrap()
+    };
+    let mut orders = [order(1, 1), order(1, 2), order(2, 1), order(2, 2)].into_iter();
+    let first = orders.next().unwrap();
+    first.is_ne() && orders.all(|order| order == first)
+}
+
+// Where a face crosses another face's plane, its corners on the plane and
+// any edges passing through it. One point or two.
+fn plane_section(tri: [[f32; 3]; 3], sides: [Ordering; 3], plane: [[f32; 3]; 3]) -> Vec<ExactPoint> {
+    let mut points = Vec::with_capacity(2);
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        if sides[i].is_eq() {
+            points.push(ExactPoint::from_f32(tri[i]));
+        } else if sides[j].is_ne() && sides[i] != sides[j] {
+            points.push(segment_plane(tri[i], tri[j], plane));
+        }
+    }
+    points
+}
+
+// Coplanar faces overlap where either's edges pass through the other
+fn coplanar_contact(faces: [u32; 2], a: [[f32; 3]; 3], b: [[f32; 3]; 3]) -> Option<Contact> {
+    let axes = projection_axes(facet_normal(a[0], a[1], a[2]).map(|v| v as f64));
+    if separated(a, b, axes) || separated(b, a, axes) {
+        return None;
+    }
+
+    let (a, b) = (a.map(ExactPoint::from_f32), b.map(ExactPoint::from_f32));
+    let clip_edges = |edges: &[ExactPoint; 3], into: &[ExactPoint; 3]| -> Vec<Vec<ExactPoint>> {
+        (0..3)
+            .filter_map(|i| clip_segment(&edges[i], &edges[(i + 1) % 3], into, axes))
+            .map(|[start, end]| if start.exact == end.exact { vec![start] } else { vec![start, end] })
+            .collect()
+    };
+    Some(Contact { faces, pieces: [clip_edges(&b, &a), clip_edges(&a, &b)], coplanar: true })
+}
+
+// Whether some edge of the first triangle has all of the second on or
+// beyond it, so their interiors can't overlap
+fn separated(a: [[f32; 3]; 3], b: [[f32; 3]; 3], axes: [usize; 2]) -> bool {
+    let winding = orient2d_f32(a[0], a[1], a[2], axes);
+    (0..3).any(|i| b.iter().all(|&point| orient2d_f32(a[i], a[(i + 1) % 3], point, axes) != winding))
+}
+
+fn face_axes(points: &[ExactPoint], corners: [u32; 3]) -> [usize; 2] {
+    let [a, b, c] = corners.map(|corner| points[corner as usize].to_f32());
+    projection_axes(facet_normal(a, b, c).map(|v| v as f64))
+}
+
+fn segment_crossings(points: &[ExactPoint], corners: [u32; 3], segments: &[[u32; 2]]) -> Vec<ExactPoint> {
+    let axes = face_axes(points, corners);
+    let orient = |a: u32, b: u32, c: u32| orient2d(&points[a as usize], &points[b as usize], &points[c as usize], axes);
+    let mut crossings = Vec::new();
+    for (i, &[a, b]) in segments.iter().enumerate() {
+        for &[c, d] in &segments[i + 1..] {
+            if [a, b].iter().any(|end| [c, d].contains(end)) {
+                continue;
+            }
+            let straddles = |[a, b]: [u32; 2], [c, d]: [u32; 2]| {
+                let (first, second) = (orient(a, b, c), orient(a, b, d));
+                first.is_ne() && second.is_ne() && first != second
+            };
+            if straddles([a, b], [c, d]) && straddles([c, d], [a, b]) {
+                let [a, b, c, d] = [a, b, c, d].map(|index| &points[index as usize]);
+                crossings.push(line_intersection(a, b, c, d, axes));
+            }
+        }
+    }
+    crossings
+}
+
+// Triangulates a face around the points and segments of intersection in
+// it, keeping the segments as edges. Points are inserted one at a time,
+// then edges crossing a segment are flipped until it appears. Returns the
+// new faces, wound like the original, and the edges along the segments.
+fn split_face(points: &[ExactPoint], corners: [u32; 3], constraints: &Constraints) -> SplitFace {
+    let axes = face_axes(points, corners);
+    let mut local: Vec<u32> = corners.to_vec();
+    let mut index_of: HashMap<u32, usize> = corners.iter().enumerate().map(|(i, &corner)| (corner, i)).collect();
+    for &point in constraints.points.iter().chain(constraints.segments.iter().flatten()) {
+        index_of.entry(point).or_insert_with(|| {
+            local.push(point);
+            local.len() - 1
+        });
+    }
+
+    let point = |index: usize| &points[local[index] as usize];
+    let orient = |a: usize, b: usize, c: usize| orient2d(point(a), point(b), point(c), axes);
+    if orient(0, 1, 2).is_le() {
+        return (vec![corners], Vec::new());
+    }
+
+    // Boundary and segments broken at every point lying on them
+    let mut segments: Vec<[usize; 2]> = (0..3).map(|i| [i, (i + 1) % 3]).collect();
+    let num_boundary = segments.len();
+    segments.extend(constraints.segments.iter().map(|segment| segment.map(|end| index_of[&end])));
+    let mut edges: Vec<[usize; 2]> = Vec::new();
+    let mut seams: HashSet<[usize; 2]> = HashSet::new();
+    for (i, &[start, end]) in segments.iter().enumerate() {
+        let axis = if point(start).exact[axes[0]] != point(end).exact[axes[0]] { axes[0] } else { axes[1] };
+        let key = |index: usize| point(index).exact[axis].clone();
+        let (low, high) = if key(start) < key(end) { (key(start), key(end)) } else { (key(end), key(start)) };
+        let mut chain: Vec<usize> = (0..local.len())
+            .filter(|&other| {
+                other == start || other == end || (orient(start, end, other).is_eq() && key(other) > low && key(other) < high)
+            })
+            .collect();
+        chain.sort_by_cached_key(|&index| key(index));
+        for pair in chain.windows(2) {
+            let edge = [pair[0].min(pair[1]), pair[0].max(pair[1])];
+            if i >= num_boundary {
+                seams.insert(edge);
+            }
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+    }
+
+    let mut mesh = Triangulation::new([0, 1, 2]);
+    for (v, point) in local.iter().enumerate().skip(3) {
+        let found = mesh.tris.iter().enumerate().find_map(|(t, tri)| {
+            let sides = [0, 1, 2].map(|e| orient(tri[e], tri[(e + 1) % 3], v));
+            (!sides.contains(&Ordering::Less)).then_some((t, sides))
+        });
+        let Some((t, sides)) = found else {
+            log::warn!("Intersection point {} fell outside the face it was found in", point);
+            continue;
+        };
+        let [a, b, c] = mesh.tris[t];
+        match sides.iter().filter(|side| side.is_eq()).count() {
+            0 => {
+                mesh.set(t, [a, b, v]);
+                mesh.push([b, c, v]);
+                mesh.push([c, a, v]);
+            },
+            1 => {
+                let e = sides.iter().position(|side| side.is_eq()).unwrap();
+                let (x, y, z) = (mesh.tris[t][e], mesh.tris[t][(e + 1) % 3], mesh.tris[t][(e + 2) % 3]);
+                let neighbour = mesh.across(y, x);
+                mesh.set(t, [x, v, z]);
+                mesh.push([v, y, z]);
+                if let Some((other, w)) = neighbour {
+                    mesh.set(other, [y, v, w]);
+                    mesh.push([v, x, w]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    for &[u, w] in &edges {
+        if mesh.across(u, w).is_some() || mesh.across(w, u).is_some() {
+            continue;
+        }
+        let crosses = |a: usize, b: usize| {
+            let (first, second) = (orient(u, w, a), orient(u, w, b));
+            let (third, fourth) = (orient(a, b, u), orient(a, b, w));
+            first.is_ne() && second.is_ne() && first != second && third.is_ne() && fourth.is_ne() && third != fourth
+        };
+        let Some(mut crossing) = crossed_edges(&mesh, u, w, crosses) else {
+            log::warn!("Couldn't recover an intersection edge in face {:?}", corners);
+            continue;
+        };
+
+        // Sloan's method, each flip takes an edge crossing the segment out of
+        // the way, and an edge that can't be flipped yet goes to the back of
+        // the queue. There's always one that can go, the limit is a safeguard.
+        let mut steps_left = 16 + crossing.len().pow(3);
+        while let Some([x, y]) = crossing.pop_front() {
+            let (Some((t, a)), Some((other, b))) = (mesh.across(x, y), mesh.across(y, x)) else {
+                break;
+            };
+            let (first, second) = (orient(a, b, x), orient(a, b, y));
+            if first.is_ne() && second.is_ne() && first != second {
+                mesh.set(t, [a, x, b]);
+                mesh.set(other, [b, y, a]);
+                if crosses(a, b) {
+                    crossing.push_back([a, b]);
+                }
+            } else {
+                crossing.push_back([x, y]);
+            }
+            steps_left -= 1;
+            if steps_left == 0 {
+                break;
+            }
+        }
+        if mesh.across(u, w).is_none() && mesh.across(w, u).is_none() {
+            log::warn!("Gave up recovering an intersection edge in face {:?}", corners);
+        }
+    }
+
+    let global = |index: usize| local[index];
+    (
+        mesh.tris.into_iter().map(|tri| tri.map(global)).collect(),
+        seams.into_iter().map(|edge| edge.map(global)).collect(),
+    )
+}
+
+// The triangles of a face being split, along with the one to the left of
+// every edge they have, by its corners in winding order
+struct Triangulation {
+    tris: Vec<[usize; 3]>,
+    left: HashMap<[usize; 2], usize>,
+}
+
+impl Triangulation {
+    fn new(tri: [usize; 3]) -> Self {
+        let mut mesh = Triangulation { tris: Vec::new(), left: HashMap::new() };
+        mesh.push(tri);
+        mesh
+    }
+
+    fn push(&mut self, tri: [usize; 3]) {
+        self.tris.push(tri);
+        self.set(self.tris.len() - 1, tri);
+    }
+
+    fn set(&mut self, t: usize, tri: [usize; 3]) {
+        let old = self.tris[t];
+        for e in 0..3 {
+            let edge = [old[e], old[(e + 1) % 3]];
+            if self.left.get(&edge) == Some(&t) {
+                self.left.remove(&edge);
+            }
+        }
+        self.tris[t] = tri;
+        for e in 0..3 {
+            self.left.insert([tri[e], tri[(e + 1) % 3]], t);
+        }
+    }
+
+    // The triangle with the edge from a to b and its third corner
+    fn across(&self, a: usize, b: usize) -> Option<(usize, usize)> {
+        let t = *self.left.get(&[a, b])?;
+        let tri = self.tris[t];
+        let e = (0..3).find(|&e| tri[e] == a).unwrap();
+        Some((t, tri[(e + 2) % 3]))
+    }
+}
+
+// The edges a segment between two vertices crosses, in order from u to w,
+// found by stepping from one triangle to the next along it
+fn crossed_edges(mesh: &Triangulation, u: usize, w: usize, crosses: impl Fn(usize, usize) -> bool) -> Option<VecDeque<[usize; 2]>> {
+    let mut edge = mesh.tris.iter().find_map(|tri| {
+        let e = (0..3).find(|&e| tri[e] == u)?;
+        let (x, y) = (tri[(e + 1) % 3], tri[(e + 2) % 3]);
+        crosses(x, y).then_some([x, y])
+    })?;
+    let mut crossing = VecDeque::new();
+    loop {
+        crossing.push_back(edge);
+        let [x, y] = edge;
+        let (_, b) = mesh.across(y, x)?;
+        if b == w {
+            return Some(crossing);
+        }
+        edge = if crosses(x, b) {
+            [x, b]
+        } else if crosses(b, y) {
+            [b, y]
+        } else {
+            return None;
+        };
+        if crossing.len() > mesh.tris.len() {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The face from (0, 0) to (16, 0) to (0, 16), followed by the points given
+    fn face_points(extra: &[[f32; 2]]) -> Vec<ExactPoint> {
+        [[0.0, 0.0], [16.0, 0.0], [0.0, 16.0]].iter()
+            .chain(extra)
+            .map(|&[x, y]| ExactPoint::from_f32([x, y, 0.0]))
+            .collect()
+    }
+
+    // Splits the face and checks the pieces cover it exactly once, wound the
+    // same way, with every seam an edge of them
+    fn split(points: &[ExactPoint], constraints: &Constraints) -> SplitFace {
+        let (tris, seams) = split_face(points, [0, 1, 2], constraints);
+        let axes = face_axes(points, [0, 1, 2]);
+        let area = |[a, b, c]: [u32; 3]| {
+            let [a, b, c] = [a, b, c].map(|index| points[index as usize].approx);
+            ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])) / 2.0
+        };
+        assert!(tris.iter().all(|&[a, b, c]| {
+            orient2d(&points[a as usize], &points[b as usize], &points[c as usize], axes).is_gt()
+        }));
+        assert_eq!(tris.iter().map(|&tri| area(tri)).sum::<f64>(), 128.0);
+        let has_edge = |[a, b]: [u32; 2]| tris.iter().any(|tri| tri.contains(&a) && tri.contains(&b));
+        assert!(seams.iter().all(|&seam| has_edge(seam)));
+        (tris, seams)
+    }
+
+    fn sorted(mut seams: Vec<[u32; 2]>) -> Vec<[u32; 2]> {
+        seams.iter_mut().for_each(|seam| seam.sort_unstable());
+        seams.sort_unstable();
+        seams
+    }
+
+    #[test]
+    fn keeps_a_segment_through_the_face() {
+        let points = face_points(&[[1.0, 1.0], [9.0, 2.0], [4.0, 3.0], [5.0, 0.5]]);
+        let constraints = Constraints { points: vec![5, 6], segments: vec![[3, 4]] };
+        let (tris, seams) = split(&points, &constraints);
+        assert_eq!(tris.len(), 9);
+        assert_eq!(seams, vec![[3, 4]]);
+    }
+
+    #[test]
+    fn splits_crossing_segments_where_they_cross() {
+        let mut points = face_points(&[[1.0, 1.0], [9.0, 3.0], [2.0, 5.0], [6.0, 0.5]]);
+        let segments = vec![[3, 4], [5, 6]];
+        let crossings = segment_crossings(&points, [0, 1, 2], &segments);
+        assert_eq!(crossings.len(), 1);
+        points.extend(crossings);
+        let (_, seams) = split(&points, &Constraints { points: vec![7], segments });
+        assert_eq!(sorted(seams), vec![[3, 7], [4, 7], [5, 7], [6, 7]]);
+    }
+
+    #[test]
+    fn breaks_collinear_segments_at_every_point_on_them() {
+        // Two overlapping segments along y = 1 with a point between, and one
+        // lying along the edge of the face
+        let points = face_points(&[[1.0, 1.0], [2.0, 1.0], [3.0, 1.0], [4.0, 1.0], [2.5, 1.0], [2.0, 0.0], [5.0, 0.0]]);
+        let constraints = Constraints { points: vec![7], segments: vec![[3, 5], [4, 6], [8, 9]] };
+        let (_, seams) = split(&points, &constraints);
+        assert_eq!(sorted(seams), vec![[3, 4], [4, 7], [5, 6], [5, 7], [8, 9]]);
+    }
+
+    #[test]
+    fn recovers_a_segment_crossing_many_triangles() {
+        // Points zigzagging either side of the segment, which the insertion
+        // order leaves joined by edges across it
+        let mut extra: Vec<[f32; 2]> = (1..12).map(|i| [i as f32, if i % 2 == 0 { 4.25 } else { 3.75 }]).collect();
+        extra.extend([[0.5, 4.0], [11.5, 4.0]]);
+        let points = face_points(&extra);
+        let constraints = Constraints { points: (3..14).collect(), segments: vec![[14, 15]] };
+        let (_, seams) = split(&points, &constraints);
+        assert_eq!(seams, vec![[14, 15]]);
+    }
+
+    #[test]
+    fn splits_crossing_faces() {
+        // Two triangles crossing each other through the middle
+        let mut mesh = TriMesh::new("cross");
+        mesh.positions = vec![
+            [0.0, 0.0, 0.0], [4.0, 0.0, 0.0], [0.0, 4.0, 0.0],
+            [1.0, 1.0, -1.0], [1.0, 1.0, 1.0], [5.0, 5.0, 0.0],
+        ];
+        mesh.triangles = vec![[0, 1, 2], [3, 5, 4]];
+        let arrangement = arrange(&mesh);
+        assert_eq!((arrangement.intersecting_pairs, arrangement.split_faces), (1, 2));
+        assert_eq!(arrangement.seams.len(), 1);
+        assert!(arrangement.coplanar.is_empty());
+        let seam = arrangement.seams.iter().next().unwrap();
+        let ends = seam.map(|end| arrangement.points[end as usize].to_f32());
+        assert!(ends.contains(&[1.0, 1.0, 0.0]) && ends.contains(&[2.0, 2.0, 0.0]));
+    }
+}
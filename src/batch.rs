@@ -0,0 +1,270 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::cavity::{fill_scene, FillMode};
+use crate::format::{load, save};
+
+pub struct BatchOptions {
+    // Matched against the path relative to the input directory
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub mode: FillMode,
+    pub weld_epsilon: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileSummary {
+    pub triangles: usize,
+    pub cavities_found: usize,
+    pub cavities_filled: usize,
+    pub added_volume: f64,
+}
+
+pub struct BatchResult {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub outcome: Result<FileSummary, String>,
+}
+
+pub struct BatchReport {
+    pub root: PathBuf,
+    pub results: Vec<BatchResult>,
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    // Writing inside the input tree would overwrite the sources or pick up
+    // the outputs as inputs, and the reverse would mirror into itself
+    OverlappingDirectories { input: PathBuf, output: PathBuf },
+    Io { path: PathBuf, error: std::io::Error },
+}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+// Every file under root whose relative path matches an include pattern
+// and no exclude pattern, sorted so runs are reproducible. A directory
+// that can't be read fails the lot rather than quietly leaving files out.
+pub fn find_inputs(root: &Path, include: &[Pattern], exclude: &[Pattern]) -> Result<Vec<PathBuf>, BatchError> {
+    let mut inputs = Vec::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry.map_err(|error| BatchError::Io {
+            path: error.path().unwrap_or(root).to_path_buf(),
+            error: error.into(),
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = entry.into_path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if include.iter().any(|pattern| pattern.matches_path_with(relative, MATCH_OPTIONS))
+            && !exclude.iter().any(|pattern| pattern.matches_path_with(relative, MATCH_OPTIONS))
+        {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+// Fills every matching file under root in parallel, writing results to the
+// same relative path under output_root. A bad file is recorded in the
+// report and doesn't stop the rest of the batch. Refuses to run when
+// either directory is the other or inside it.
+pub fn run_batch(root: &Path, output_root: &Path, options: &BatchOptions) -> Result<BatchReport, BatchError> {
+    let (input, output) = (resolve(root)?, resolve(output_root)?);
+    if output.starts_with(&input) || input.starts_with(&output) {
+        return Err(BatchError::OverlappingDirectories { input, output });
+    }
+
+    let results = find_inputs(root, &options.include, &options.exclude)?
+        .into_par_iter()
+        .map(|input| {
+            let relative = input.strip_prefix(root).unwrap_or(&input);
+            let output = output_root.join(relative);
+            let outcome = process_file(&input, &output, options);
+            match &outcome {
+                Ok(summary) => log::info!("{}: filled {} cavities", input.display(), summary.cavities_filled),
+                Err(e) => log::warn!("{}: {}", input.display(), e),
+            }
+            BatchResult { input, output, outcome }
+        })
+        .collect();
+
+    Ok(BatchReport { root: root.to_path_buf(), results })
+}
+
+// Canonical form of a path that may not exist yet, resolving the part that
+// does so symlinks and relative components can't hide an overlap
+fn resolve(path: &Path) -> Result<PathBuf, BatchError> {
+    let absolute = std::path::absolute(path).map_err(|error| BatchError::Io { path: path.to_path_buf(), error })?;
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            },
+            _ => break,
+        }
+    }
+    let mut resolved = existing.canonicalize().map_err(|error| BatchError::Io { path: path.to_path_buf(), error })?;
+    resolved.extend(missing.iter().rev());
+    Ok(resolved)
+}
+
+fn process_file(input: &Path, output: &Path, options: &BatchOptions) -> Result<FileSummary, String> {
+    let scene = load(input).map_err(|e| e.to_string())?;
+    let (solid, summaries) = fill_scene(&scene, options.mode, options.weld_epsilon).map_err(|e| e.to_string())?;
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    save(&solid, output).map_err(|e| e.to_string())?;
+
+    Ok(FileSummary {
+        triangles: solid.num_triangles(),
+        cavities_found: summaries.iter().map(|summary| summary.report.cavities().count()).sum(),
+        cavities_filled: summaries.iter().map(|summary| summary.filled.len()).sum(),
+        added_volume: summaries.iter().map(|summary| summary.added_volume).sum(),
+    })
+}
+
+impl BatchReport {
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|result| result.outcome.is_err()).count()
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::OverlappingDirectories { input, output } => write!(
+                f, "output directory {} overlaps input directory {}, pick one outside it", output.display(), input.display()),
+            BatchError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BatchError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.results.iter()
+            .map(|result| result.input.strip_prefix(&self.root).unwrap_or(&result.input).display().to_string())
+            .collect();
+        let width = names.iter().map(String::len).max().unwrap_or(0).max("file".len());
+
+        writeln!(f, "{:<width$}  {:>9}  {:>5}  {:>6}  {:>12}", "file", "triangles", "found", "filled", "added volume")?;
+        for (name, result) in names.iter().zip(&self.results) {
+            match &result.outcome {
+                Ok(summary) => writeln!(
+                    f,
+                    "{:<width$}  {:>9}  {:>5}  {:>6}  {:>12.3}",
+                    name, summary.triangles, summary.cavities_found, summary.cavities_filled, summary.added_volume
+                )?,
+                Err(e) => writeln!(f, "{:<width$}  failed: {}", name, e)?,
+            }
+        }
+
+        let succeeded: Vec<&FileSummary> = self.results.iter()
+            .filter_map(|result| result.outcome.as_ref().ok())
+            .collect();
+        write!(
+            f,
+            "{} files, {} failed, {} cavities found, {} filled, {:.3} volume added",
+            self.results.len(),
+            self.failures(),
+            succeeded.iter().map(|summary| summary.cavities_found).sum::<usize>(),
+            succeeded.iter().map(|summary| summary.cavities_filled).sum::<usize>(),
+            succeeded.iter().map(|summary| summary.added_volume).sum::<f64>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+    use crate::mesh::Scene;
+
+    fn options() -> BatchOptions {
+        BatchOptions {
+            include: vec![Pattern::new("*.stl").unwrap()],
+            exclude: Vec::new(),
+            mode: FillMode::All,
+            weld_epsilon: 0.0,
+        }
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = temp_path(name);
+        std::fs::create_dir_all(dir.join("models")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn refuses_overlapping_directories() {
+        let dir = scratch("batch_overlap");
+        let input = dir.join("models");
+        for output in [input.clone(), input.join("solid"), dir.clone(), input.join("..").join("models")] {
+            let result = run_batch(&input, &output, &options());
+            assert!(matches!(result, Err(BatchError::OverlappingDirectories { .. })), "{}", output.display());
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mirrors_nested_files_into_the_output_directory() {
+        let dir = scratch("batch_nested");
+        let nested = dir.join("models").join("parts").join("inner");
+        std::fs::create_dir_all(&nested).unwrap();
+        let mut hollow = cube("hollow", [0.0; 3], [10.0; 3]);
+        hollow.append(&cube("hollow", [3.0; 3], [7.0; 3]));
+        save(&Scene { header: Vec::new(), meshes: vec![hollow] }, nested.join("hollow.stl")).unwrap();
+        std::fs::write(dir.join("models").join("parts").join("corrupt.stl"), b"solid broken\nfacet normal nonsense\n").unwrap();
+        std::fs::write(nested.join("notes.txt"), b"not a model").unwrap();
+
+        let output_root = dir.join("models_solid");
+        let report = run_batch(&dir.join("models"), &output_root, &options()).unwrap();
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.failures(), 1);
+
+        let [corrupt, hollow] = &report.results[..] else { unreachable!() };
+        assert!(corrupt.input.ends_with("parts/corrupt.stl"));
+        assert!(corrupt.outcome.is_err());
+        assert!(!corrupt.output.exists());
+
+        let summary = hollow.outcome.as_ref().unwrap();
+        assert_eq!(hollow.output, output_root.join("parts").join("inner").join("hollow.stl"));
+        assert_eq!((summary.triangles, summary.cavities_found, summary.cavities_filled), (12, 1, 1));
+        assert!((summary.added_volume - 64.0).abs() < 1e-6);
+        let written = load(&hollow.output).unwrap();
+        assert_eq!(written.num_triangles(), 12);
+        assert!((written.meshes[0].signed_volume() - 1000.0).abs() < 1e-6);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fails_on_a_missing_input_directory() {
+        let dir = scratch("batch_missing");
+        let result = run_batch(&dir.join("missing"), &dir.join("models_solid"), &options());
+        assert!(matches!(result, Err(BatchError::Io { .. })));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::mesh::TriMesh;
+use crate::topology::Topology;
+use crate::union::{resolve, Sides, UnionError};
+use crate::weld::weld;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum BooleanOp {
+    Union,
+    // The first mesh with the second cut out of it
+    Difference,
+    Intersection,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BooleanReport {
+    pub operation: BooleanOp,
+    // Pairs of faces, one from either mesh or both from the same, crossing
+    // or overlapping each other
+    pub intersecting_pairs: usize,
+    pub split_faces: usize,
+    pub removed_triangles: usize,
+    // Faces of the second mesh turned around to face out of a difference
+    pub flipped_triangles: usize,
+}
+
+#[derive(Debug)]
+pub enum BooleanError {
+    // Inside and outside mean nothing for a mesh with holes in it
+    NotClosed { name: String, boundary_edges: usize },
+    Union(UnionError),
+}
+
+// Combines two welded, closed and outward wound meshes. Faces are split
+// exactly where the meshes cross, then each patch between the crossings is
+// kept if the solid result lies on one side of it and not the other, and
+// turned around if that's in front of it. The result has the first mesh's
+// name.
+pub fn boolean(first: &TriMesh, second: &TriMesh, operation: BooleanOp) -> Result<(TriMesh, BooleanReport), BooleanError> {
+    for mesh in [first, second] {
+        let boundary_edges = Topology::new(mesh).boundary_edges().len();
+        if boundary_edges > 0 {
+            return Err(BooleanError::NotClosed { name: mesh.name.clone(), boundary_edges });
+        }
+    }
+
+    // Welding shares the vertices the meshes have in common, without
+    // dropping any faces since neither mesh has degenerate ones
+    let mut combined = first.clone();
+    combined.append(second);
+    let combined = weld(&combined, 0.0);
+    let operands: Vec<usize> = (0..combined.num_triangles())
+        .map(|face| usize::from(face >= first.num_triangles()))
+        .collect();
+
+    let inside = |[a, b]: [bool; 2]| match operation {
+        BooleanOp::Union => a || b,
+        BooleanOp::Difference => a && !b,
+        BooleanOp::Intersection => a && b,
+    };
+    let decide = |sides: &Sides| {
+        match (inside([sides.front[0], sides.front[1]]), inside([sides.behind[0], sides.behind[1]])) {
+            (false, true) => Some(false),
+            (true, false) => Some(true),
+            _ => None,
+        }
+    };
+    let (mut result, resolution) = resolve(&combined, &operands, 2, decide)?;
+    result.name = first.name.clone();
+
+    let report = BooleanReport {
+        operation,
+        intersecting_pairs: resolution.intersecting_pairs,
+        split_faces: resolution.split_faces,
+        removed_triangles: resolution.removed_triangles,
+        flipped_triangles: resolution.flipped_triangles,
+    };
+    Ok((result, report))
+}
+
+impl fmt::Display for BooleanOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BooleanOp::Union => "union",
+            BooleanOp::Difference => "difference",
+            BooleanOp::Intersection => "intersection",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for BooleanOp {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "union" => Ok(BooleanOp::Union),
+            "difference" => Ok(BooleanOp::Difference),
+            "intersection" => Ok(BooleanOp::Intersection),
+            _ => Err(format!("{} is not union, difference or intersection", name)),
+        }
+    }
+}
+
+impl fmt::Display for BooleanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} split {} faces where {} pairs cross, removed {} triangles and turned {} around",
+            self.operation, self.split_faces, self.intersecting_pairs, self.removed_triangles, self.flipped_triangles
+        )
+    }
+}
+
+impl fmt::Display for BooleanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BooleanError::NotClosed { name, boundary_edges } => {
+                write!(f, "{} is not closed, it has {} boundary edges", name, boundary_edges)
+            },
+            BooleanError::Union(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BooleanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BooleanError::Union(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<UnionError> for BooleanError {
+    fn from(e: UnionError) -> Self {
+        BooleanError::Union(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    fn combine(operation: BooleanOp) -> (TriMesh, BooleanReport) {
+        boolean(&cube("a", [0.0; 3], [10.0; 3]), &cube("b", [5.0; 3], [15.0; 3]), operation).unwrap()
+    }
+
+    #[test]
+    fn combines_overlapping_cubes() {
+        for (operation, volume) in [
+            (BooleanOp::Union, 1875.0),
+            (BooleanOp::Difference, 875.0),
+            (BooleanOp::Intersection, 125.0),
+        ] {
+            let (result, report) = combine(operation);
+            assert_eq!(result.name, "a");
+            assert!(report.intersecting_pairs > 0, "{}", operation);
+            assert!(Topology::new(&result).boundary_edges().is_empty(), "{} isn't closed", operation);
+            assert!((result.signed_volume() - volume).abs() < 1e-3, "{} has volume {}", operation, result.signed_volume());
+        }
+    }
+
+    #[test]
+    fn turns_the_cut_around_for_a_difference() {
+        assert!(combine(BooleanOp::Difference).1.flipped_triangles > 0);
+        assert_eq!(combine(BooleanOp::Union).1.flipped_triangles, 0);
+    }
+
+    #[test]
+    fn cuts_a_cavity_out_of_a_cube() {
+        let (result, _) = boolean(&cube("a", [0.0; 3], [10.0; 3]), &cube("b", [3.0; 3], [7.0; 3]), BooleanOp::Difference).unwrap();
+        assert_eq!(result.num_triangles(), 24);
+        assert!((result.signed_volume() - 936.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn refuses_open_meshes() {
+        let mut open = cube("open", [0.0; 3], [10.0; 3]);
+        open.triangles.pop();
+        match boolean(&open, &cube("b", [5.0; 3], [15.0; 3]), BooleanOp::Union) {
+            Err(BooleanError::NotClosed { name, boundary_edges: 3 }) => assert_eq!(name, "open"),
+            other => panic!("expected the open mesh to be refused, got {:?}", other.map(|(_, report)| report)),
+        }
+    }
+
+    #[test]
+    fn parses_operations() {
+        for operation in [BooleanOp::Union, BooleanOp::Difference, BooleanOp::Intersection] {
+            assert_eq!(operation.to_string().parse::<BooleanOp>(), Ok(operation));
+        }
+        assert!("xor".parse::<BooleanOp>().is_err());
+    }
+}
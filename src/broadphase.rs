@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::mesh::TriMesh;
+
+// A face covering more grid cells than this is checked against every other
+// face instead. One huge triangle among lots of small ones would otherwise
+// fill the grid with billions of entries.
+const MAX_CELLS_PER_FACE: u64 = 64;
+
+// Every pair of faces whose bounding boxes overlap or touch, each pair once
+// with the lower face first, in order. Faces are bucketed on a uniform grid
+// about the size of an average triangle, so this stays close to linear on
+// meshes that don't pile lots of triangles into one place and have only a
+// few faces much bigger than the rest.
+pub fn overlapping_pairs(mesh: &TriMesh) -> Vec<[u32; 2]> {
+    let num_triangles = mesh.num_triangles();
+    if num_triangles < 2 {
+        return Vec::new();
+    }
+
+    let boxes: Vec<([f32; 3], [f32; 3])> = (0..num_triangles)
+        .map(|face| {
+            let [a, b, c] = mesh.triangle(face);
+            ([0, 1, 2].map(|i| a[i].min(b[i]).min(c[i])), [0, 1, 2].map(|i| a[i].max(b[i]).max(c[i])))
+        })
+        .collect();
+    let mean_extent = boxes.iter()
+        .map(|(min, max)| (0..3).map(|i| (max[i] - min[i]) as f64).fold(0.0, f64::max))
+        .sum::<f64>() / num_triangles as f64;
+    let cell_size = if mean_extent > 0.0 { mean_extent } else { 1.0 };
+    let cell_of = |position: [f32; 3]| position.map(|v| (v as f64 / cell_size).floor() as i64);
+
+    let overlap = |a: u32, b: u32| {
+        let (box_a, box_b) = (boxes[a as usize], boxes[b as usize]);
+        let overlap_min: [f32; 3] = [0, 1, 2].map(|k| box_a.0[k].max(box_b.0[k]));
+        (0..3).all(|k| overlap_min[k] <= box_a.1[k].min(box_b.1[k])).then_some(overlap_min)
+    };
+
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+    let mut oversized = Vec::new();
+    for (face, (min, max)) in boxes.iter().enumerate() {
+        let (low, high) = (cell_of(*min), cell_of(*max));
+        let cells = (0..3).fold(1u64, |cells, i| cells.saturating_mul(high[i].abs_diff(low[i]).saturating_add(1)));
+        if cells > MAX_CELLS_PER_FACE {
+            oversized.push(face as u32);
+            continue;
+        }
+        for x in low[0]..=high[0] {
+            for y in low[1]..=high[1] {
+                for z in low[2]..=high[2] {
+                    grid.entry([x, y, z]).or_default().push(face as u32);
+                }
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (cell, faces) in &grid {
+        for (i, &a) in faces.iter().enumerate() {
+            for &b in &faces[i + 1..] {
+                // Each pair is only reported from the first cell both boxes share
+                if overlap(a, b).is_some_and(|overlap_min| cell_of(overlap_min) == *cell) {
+                    pairs.push([a.min(b), a.max(b)]);
+                }
+            }
+        }
+    }
+    for (i, &a) in oversized.iter().enumerate() {
+        let is_oversized = |b: u32| oversized.binary_search(&b).is_ok();
+        // Pairs of two oversized faces come from the first of them only
+        let others = (0..num_triangles as u32).filter(|&b| !is_oversized(b)).chain(oversized[i + 1..].iter().copied());
+        for b in others {
+            if overlap(a, b).is_some() {
+                pairs.push([a.min(b), a.max(b)]);
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    fn all_pairs(mesh: &TriMesh) -> Vec<[u32; 2]> {
+        let bounds = |face: u32| {
+            let corners = mesh.triangle(face as usize);
+            let min = [0, 1, 2].map(|i| corners.iter().map(|c| c[i]).fold(f32::MAX, f32::min));
+            let max = [0, 1, 2].map(|i| corners.iter().map(|c| c[i]).fold(f32::MIN, f32::max));
+            (min, max)
+        };
+        let count = mesh.num_triangles() as u32;
+        (0..count)
+            .flat_map(|a| (a + 1..count).map(move |b| [a, b]))
+            .filter(|&[a, b]| {
+                let (box_a, box_b) = (bounds(a), bounds(b));
+                (0..3).all(|i| box_a.0[i].max(box_b.0[i]) <= box_a.1[i].min(box_b.1[i]))
+            })
+            .collect()
+    }
+
+    // Small triangles spread over a square of the given size, in the z = 0 plane
+    fn scattered(size: usize, spacing: f32) -> TriMesh {
+        let mut mesh = TriMesh::new("scattered");
+        for x in 0..size {
+            for y in 0..size {
+                let (px, py) = (x as f32 * spacing, y as f32 * spacing);
+                let first = mesh.positions.len() as u32;
+                mesh.positions.extend([[px, py, 0.0], [px + 0.5, py, 0.0], [px, py + 0.5, 0.1]]);
+                mesh.triangles.push([first, first + 1, first + 2]);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn finds_every_overlapping_pair_once() {
+        let mut mesh = cube("cubes", [0.0; 3], [1.0; 3]);
+        // Overlaps the first, touches it along x = 1, and is off by itself
+        for (min, max) in [([0.5; 3], [1.5; 3]), ([1.0, 0.0, 0.0], [2.0, 1.0, 1.0]), ([5.0; 3], [6.0; 3])] {
+            let other = cube("cubes", min, max);
+            let offset = mesh.positions.len() as u32;
+            mesh.positions.extend(other.positions);
+            mesh.triangles.extend(other.triangles.iter().map(|tri| tri.map(|v| v + offset)));
+        }
+        let pairs = overlapping_pairs(&mesh);
+        assert_eq!(pairs, all_pairs(&mesh));
+        // Only the last cube's own faces meet it
+        assert!(pairs.iter().all(|&[a, b]| (a < 36) == (b < 36)));
+    }
+
+    #[test]
+    fn handles_a_huge_face_among_small_ones() {
+        // Spaced out so that no two small ones overlap each other
+        let mut mesh = scattered(40, 1.0);
+        let first = mesh.positions.len() as u32;
+        mesh.positions.extend([[-1e6, -1e6, -1.0], [1e6, -1e6, 1.0], [0.0, 1e6, 0.0]]);
+        mesh.triangles.push([first, first + 1, first + 2]);
+        mesh.positions.extend([[-1e6, -1e6, 0.0], [1e6, -1e6, 0.0], [0.0, 1e6, 0.0]]);
+        mesh.triangles.push([first + 3, first + 4, first + 5]);
+
+        let pairs = overlapping_pairs(&mesh);
+        assert_eq!(pairs, all_pairs(&mesh));
+        // Each huge face overlaps every small one and the other huge one
+        assert_eq!(pairs.len(), 2 * 1600 + 1);
+    }
+
+    #[test]
+    fn needs_two_faces() {
+        assert!(overlapping_pairs(&scattered(1, 1.0)).is_empty());
+        assert!(overlapping_pairs(&TriMesh::new("empty")).is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::cleanup::clean;
+use crate::mesh::{triangle_area, Bounds, Scene, TriMesh};
+use crate::orient::orient;
+use crate::shell::{extract_shells, Shell};
+use crate::topology::Topology;
+use crate::union::{self_union, UnionError, UnionReport};
+use crate::weld::weld;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellKind {
+    // Boundary between outside air (or a cavity) and solid material
+    Outer,
+    // Boundary of an empty pocket enclosed by solid material
+    Cavity,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ShellReport {
+    pub name: String,
+    pub kind: ShellKind,
+    // How many other shells this one sits inside of
+    pub depth: usize,
+    // Index of the innermost shell containing this one
+    pub container: Option<usize>,
+    pub volume: f64,
+    pub bounds: Bounds,
+    pub closed: bool,
+    // Negative signed volume, what a correctly wound cavity looks like
+    pub inward_facing: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillMode {
+    // Fill every enclosed cavity
+    All,
+    // Only fill cavities with less volume than this, bigger ones are
+    // usually deliberate hollowing
+    BelowVolume(f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct FillSummary {
+    pub report: CavityReport,
+    // Indices into report.shells of the cavities that were filled
+    pub filled: Vec<usize>,
+    // Cavities plus anything floating inside them
+    pub removed_shells: usize,
+    // Resin the filled cavities add to the print
+    pub added_volume: f64,
+    // Overlapping parts merged before looking for cavities
+    pub union: UnionReport,
+}
+
+#[derive(Clone, Debug)]
+pub struct CavityReport {
+    // Same order as the shells that were classified
+    pub shells: Vec<ShellReport>,
+}
+
+impl CavityReport {
+    pub fn cavities(&self) -> impl Iterator<Item = (usize, &ShellReport)> {
+        self.shells.iter()
+            .enumerate()
+            .filter(|(_, shell)| shell.kind == ShellKind::Cavity)
+    }
+
+    // Shells whose innermost container is the given shell
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.shells.iter()
+            .enumerate()
+            .filter(move |(_, shell)| shell.container == Some(index))
+            .map(|(child, _)| child)
+    }
+
+    // Volume of material already inside a shell, islands inside cavities
+    // inside islands and so on
+    pub fn solid_volume_inside(&self, index: usize) -> f64 {
+        match self.shells[index].kind {
+            ShellKind::Outer => self.shells[index].volume - self.children(index)
+                .map(|cavity| self.shells[cavity].volume - self.solid_volume_inside(cavity))
+                .sum::<f64>(),
+            ShellKind::Cavity => self.children(index)
+                .map(|island| self.solid_volume_inside(island))
+                .sum(),
+        }
+    }
+
+    fn is_inside(&self, index: usize, ancestor: usize) -> bool {
+        let mut current = self.shells[index].container;
+        while let Some(container) = current {
+            if container == ancestor {
+                return true;
+            }
+            current = self.shells[container].container;
+        }
+        false
+    }
+}
+
+impl fmt::Display for FillSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.union.removed_triangles > 0 {
+            write!(f, "{}", self.union)?;
+        }
+        write!(f, "{}", self.report)?;
+        write!(
+            f,
+            "filled {} cavities ({} shells removed), adding {:.3} of resin",
+            self.filled.len(), self.removed_shells, self.added_volume
+        )
+    }
+}
+
+impl fmt::Display for CavityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} shells, {} cavities", self.shells.len(), self.cavities().count())?;
+        for (index, shell) in self.cavities() {
+            writeln!(
+                f,
+                "  cavity {} ({}): volume {:.3}, bounds {:?} to {:?}{}{}",
+                index,
+                shell.name,
+                shell.volume,
+                shell.bounds.min,
+                shell.bounds.max,
+                if shell.closed { "" } else { ", open" },
+                if shell.inward_facing { "" } else { ", outward facing" },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A shell nested inside an odd number of other shells bounds empty space
+// carved out of the solid around it, an even number means it is solid
+// (an outer surface, or an island floating inside a cavity). Orientation
+// is reported alongside but containment decides, since plenty of files
+// have cavities wound the same way as the outside.
+pub fn find_cavities(shells: &[Shell]) -> CavityReport {
+    let samples: Vec<[f32; 3]> = shells.iter().map(|shell| sample_point(&shell.mesh)).collect();
+
+    let containers: Vec<Vec<usize>> = shells.iter()
+        .enumerate()
+        .map(|(index, shell)| {
+            shells.iter()
+                .enumerate()
+                .filter(|&(other, other_shell)| {
+                    other != index
+                        && other_shell.stats.bounds.contains(&shell.stats.bounds)
+                        && contains_point(&other_shell.mesh, samples[index])
+                })
+                .map(|(other, _)| other)
+                .collect()
+        })
+        .collect();
+
+    let shells = shells.iter()
+        .zip(&containers)
+        .map(|(shell, containers)| {
+            let depth = containers.len();
+            ShellReport {
+                name: shell.mesh.name.clone(),
+                kind: if depth % 2 == 1 { ShellKind::Cavity } else { ShellKind::Outer },
+                depth,
+                container: containers.iter()
+                    .copied()
+                    .min_by(|&a, &b| shells[a].stats.surface_area.total_cmp(&shells[b].stats.surface_area)),
+                volume: shell.stats.signed_volume.abs(),
+                bounds: shell.stats.bounds,
+                closed: Topology::new(&shell.mesh).boundary_edges().is_empty(),
+                inward_facing: shell.stats.signed_volume < 0.0,
+            }
+        })
+        .collect();
+
+    CavityReport { shells }
+}
+
+// Removes enclosed cavities from a welded mesh so it prints solid. Filling
+// a cavity also drops anything floating inside it, which would otherwise
+// be left as internal faces.
+pub fn fill_cavities(mesh: &TriMesh, mode: FillMode) -> (TriMesh, FillSummary) {
+    let shells = extract_shells(mesh);
+    let report = find_cavities(&shells);
+
+    let filled: Vec<usize> = report.cavities()
+        .filter(|(_, shell)| match mode {
+            FillMode::All => true,
+            FillMode::BelowVolume(threshold) => shell.volume < threshold,
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    // Only count a cavity once even if it sits inside another filled cavity
+    let added_volume = filled.iter()
+        .filter(|&&index| !filled.iter().any(|&other| report.is_inside(index, other)))
+        .map(|&index| report.shells[index].volume - report.solid_volume_inside(index))
+        .sum();
+
+    let mut solid = TriMesh::new(&mesh.name);
+    let mut removed_shells = 0;
+    for (index, shell) in shells.iter().enumerate() {
+        if filled.iter().any(|&cavity| cavity == index || report.is_inside(index, cavity)) {
+            removed_shells += 1;
+        } else {
+            solid.append(&shell.mesh);
+        }
+    }
+
+    (solid, FillSummary { report, filled, removed_shells, added_volume, union: UnionReport::default() })
+}
+
+// Welds, cleans up and fills every mesh in a scene, one summary per mesh.
+// Overlapping parts are merged first so the faces inside them aren't
+// mistaken for cavities. Merging goes by which way faces point, so shells
+// are oriented before it, otherwise an inside out part would vanish and a
+// cavity wound like the outside would be taken for a buried part.
+pub fn fill_scene(scene: &Scene, mode: FillMode, weld_epsilon: f32) -> Result<(Scene, Vec<FillSummary>), UnionError> {
+    let (meshes, summaries) = scene.meshes.iter()
+        .map(|mesh| {
+            let (oriented, _) = orient(&clean(&weld(mesh, weld_epsilon)).0);
+            let (merged, union) = self_union(&oriented)?;
+            let (solid, summary) = fill_cavities(&merged, mode);
+            Ok((solid, FillSummary { union, ..summary }))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    Ok((Scene { header: scene.header.clone(), meshes }, summaries))
+}
+
+// Centroid of the largest triangle, well away from any of the mesh's vertices
+fn sample_point(mesh: &TriMesh) -> [f32; 3] {
+    let largest = (0..mesh.num_triangles())
+        .max_by(|&a, &b| triangle_area(mesh.triangle(a)).total_cmp(&triangle_area(mesh.triangle(b))));
+    match largest {
+        Some(index) => {
+            let [a, b, c] = mesh.triangle(index);
+            [0, 1, 2].map(|i| (a[i] + b[i] + c[i]) / 3.0)
+        },
+        None => [0.0; 3],
+    }
+}
+
+// Generalized winding number, which degrades gracefully on meshes with
+// small holes where a ray cast would give up
+pub fn winding_number(mesh: &TriMesh, point: [f32; 3]) -> f64 {
+    let p = Vector3::from(point).cast::<f64>().unwrap();
+    let solid_angle: f64 = (0..mesh.num_triangles())
+        .map(|index| solid_angle(mesh.triangle(index), p))
+        .sum();
+    solid_angle / (4.0 * std::f64::consts::PI)
+}
+
+// Signed solid angle a triangle covers as seen from the point, positive
+// when the point is behind it (Van Oosterom and Strackee)
+pub fn solid_angle(corners: [[f32; 3]; 3], point: Vector3<f64>) -> f64 {
+    let [a, b, c] = corners.map(|v| Vector3::from(v).cast::<f64>().unwrap() - point);
+    let (la, lb, lc) = (a.magnitude(), b.magnitude(), c.magnitude());
+    let numerator = a.dot(b.cross(c));
+    let denominator = la * lb * lc + a.dot(b) * lc + b.dot(c) * la + c.dot(a) * lb;
+    2.0 * numerator.atan2(denominator)
+}
+
+// Works for either orientation, an inside-out shell still encloses its inside
+pub fn contains_point(mesh: &TriMesh, point: [f32; 3]) -> bool {
+    winding_number(mesh, point).abs() > 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, inverted};
+
+    fn scene(meshes: Vec<TriMesh>) -> Scene {
+        Scene { header: Vec::new(), meshes }
+    }
+
+    // A 10 unit cube with a 4 unit hollow in the middle, both shells
+    // wound outwards the way plenty of exporters write them
+    fn hollow_cube() -> TriMesh {
+        let mut mesh = cube("hollow", [0.0; 3], [10.0; 3]);
+        mesh.append(&cube("hollow", [3.0; 3], [7.0; 3]));
+        mesh
+    }
+
+    #[test]
+    fn keeps_inside_out_meshes() {
+        let (filled, summaries) = fill_scene(&scene(vec![inverted(cube("cube", [0.0; 3], [10.0; 3]))]), FillMode::All, 0.0).unwrap();
+        assert_eq!(filled.meshes[0].num_triangles(), 12);
+        assert!((filled.meshes[0].signed_volume() - 1000.0).abs() < 1e-6);
+        assert_eq!(summaries[0].union.removed_triangles, 0);
+    }
+
+    #[test]
+    fn finds_cavities_wound_like_the_outside() {
+        let (kept, summaries) = fill_scene(&scene(vec![hollow_cube()]), FillMode::BelowVolume(10.0), 0.0).unwrap();
+        assert_eq!(summaries[0].report.cavities().count(), 1);
+        assert!(summaries[0].filled.is_empty());
+        assert_eq!(kept.meshes[0].num_triangles(), 24);
+        assert!((kept.meshes[0].signed_volume() - 936.0).abs() < 1e-6);
+
+        let (filled, summaries) = fill_scene(&scene(vec![hollow_cube()]), FillMode::All, 0.0).unwrap();
+        assert_eq!(summaries[0].filled, vec![1]);
+        assert!((summaries[0].added_volume - 64.0).abs() < 1e-6);
+        assert_eq!(filled.meshes[0].num_triangles(), 12);
+        assert!((filled.meshes[0].signed_volume() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn refuses_non_finite_coordinates() {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        mesh.positions[2] = [f32::NAN; 3];
+        assert!(matches!(fill_scene(&scene(vec![mesh]), FillMode::All, 0.0), Err(UnionError::InvalidCoordinate { .. })));
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+
+use crate::mesh::{triangle_area, TriMesh};
+use crate::topology::Topology;
+
+// A triangle whose height over its longest edge is less than this much of
+// that edge is a needle. Thin but real faces, like the sides of a finely
+// divided cylinder, are a good few orders of magnitude wider.
+const NEEDLE_RATIO: f64 = 1e-5;
+// Only a needle with an edge this short compared to its longest gets that
+// edge collapsed. Without one it's a sliver with a corner partway along
+// the long edge, which collapsing would move a long way.
+const SHORT_EDGE_RATIO: f64 = 1e-2;
+// Fixing one bad triangle can reshape its neighbours into new ones, but
+// chains of them that long are really a broken export
+const MAX_PASSES: usize = 8;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CleanupReport {
+    // Zero-area triangles with all three corners in a line
+    pub degenerate_triangles: usize,
+    pub needle_triangles: usize,
+    // Extra copies of a face wound the same way
+    pub duplicate_faces: usize,
+    // Pairs of faces over the same vertices wound opposite ways, left behind
+    // where two closed parts were merged face to face
+    pub internal_walls: usize,
+    pub unreferenced_vertices: usize,
+}
+
+// Removes the triangles slicers and cavity detection trip over from a welded
+// mesh. Needles lose their short edge and zero-area triangles are flipped
+// into their neighbour, so neither leaves a hole behind. Normals are dropped.
+pub fn clean(mesh: &TriMesh) -> (TriMesh, CleanupReport) {
+    let mut cleaned = mesh.clone();
+    cleaned.normals = None;
+    cleaned.face_normals = None;
+    let mut report = CleanupReport::default();
+
+    for _ in 0..MAX_PASSES {
+        let needles = collapse_needles(&mut cleaned);
+        let degenerate = flip_degenerate(&mut cleaned);
+        report.needle_triangles += needles;
+        report.degenerate_triangles += degenerate;
+        if needles + degenerate == 0 {
+            break;
+        }
+    }
+
+    remove_duplicates(&mut cleaned, &mut report);
+    report.unreferenced_vertices = remove_unreferenced(&mut cleaned);
+    (cleaned, report)
+}
+
+// Edge i runs from corner i to corner i + 1
+fn edge_lengths(mesh: &TriMesh, face: usize) -> [f64; 3] {
+    let corners = mesh.triangle(face).map(|corner| Vector3::from(corner).cast::<f64>().unwrap());
+    [0, 1, 2].map(|i| (corners[(i + 1) % 3] - corners[i]).magnitude())
+}
+
+// Merges the short edge of every needle into its first vertex, which takes
+// the needle and the face across that edge with it. A collapse is refused
+// when it would join the surface to itself or turn any face around. Faces
+// near one that was just collapsed wait for the next pass.
+fn collapse_needles(mesh: &mut TriMesh) -> usize {
+    let topology = Topology::new(mesh);
+    let mut on_boundary = vec![false; mesh.positions.len()];
+    for edge in topology.edges.iter().filter(|edge| edge.is_boundary()) {
+        for vertex in edge.vertices {
+            on_boundary[vertex as usize] = true;
+        }
+    }
+    let mut remap: Vec<u32> = (0..mesh.positions.len() as u32).collect();
+    let mut touched = vec![false; mesh.positions.len()];
+    let mut collapsed = 0;
+
+    for face in 0..mesh.num_triangles() {
+        let tri = mesh.triangles[face];
+        if tri.iter().any(|&vertex| touched[vertex as usize]) {
+            continue;
+        }
+        let lengths = edge_lengths(mesh, face);
+        let shortest = (0..3).min_by(|&a, &b| lengths[a].total_cmp(&lengths[b])).unwrap();
+        let longest = lengths.iter().copied().fold(0.0, f64::max);
+        let height = 2.0 * triangle_area(mesh.triangle(face)) / longest;
+        if !(height <= NEEDLE_RATIO * longest && lengths[shortest] <= SHORT_EDGE_RATIO * longest) {
+            continue;
+        }
+
+        let (kept, removed) = (tri[shortest], tri[(shortest + 1) % 3]);
+        let edge = &topology.edges[topology.face_edges[face][shortest] as usize];
+        if !can_collapse(mesh, &topology, &on_boundary, edge.faces.as_slice(), kept, removed) {
+            continue;
+        }
+
+        remap[removed as usize] = kept;
+        for vertex in [kept, removed] {
+            touched[vertex as usize] = true;
+            for other in topology.vertex_ring(vertex) {
+                touched[other as usize] = true;
+            }
+        }
+        collapsed += 1;
+    }
+
+    if collapsed > 0 {
+        for tri in &mut mesh.triangles {
+            *tri = tri.map(|vertex| remap[vertex as usize]);
+        }
+        let keep: Vec<bool> = mesh.triangles.iter()
+            .map(|&[a, b, c]| a != b && b != c && c != a)
+            .collect();
+        retain_faces(mesh, &keep);
+    }
+    collapsed
+}
+
+// Whether merging removed into kept leaves the surface as it was around
+// them, less the faces on the edge between them. The link condition: the
+// only vertices both are joined to are the far corners of those faces, and
+// no face of either one runs between two of those corners, otherwise the
+// surface pinches into non-manifold edges. Two boundaries aren't joined
+// across the inside either, and no other face of removed may turn around.
+fn can_collapse(mesh: &TriMesh, topology: &Topology, on_boundary: &[bool], edge_faces: &[u32], kept: u32, removed: u32) -> bool {
+    if edge_faces.len() > 2 {
+        return false;
+    }
+    if edge_faces.len() == 2 && on_boundary[kept as usize] && on_boundary[removed as usize] {
+        return false;
+    }
+
+    let mut far: Vec<u32> = edge_faces.iter()
+        .filter_map(|&face| mesh.triangles[face as usize].into_iter().find(|&vertex| vertex != kept && vertex != removed))
+        .collect();
+    far.sort_unstable();
+    let removed_ring = topology.vertex_ring(removed);
+    let common: Vec<u32> = topology.vertex_ring(kept).into_iter()
+        .filter(|vertex| removed_ring.binary_search(vertex).is_ok())
+        .collect();
+    if common != far {
+        return false;
+    }
+    let spans_far = |vertex: u32| topology.vertex_faces[vertex as usize].iter()
+        .any(|&face| far.iter().filter(|&&corner| mesh.triangles[face as usize].contains(&corner)).count() == 2);
+    if far.len() == 2 && spans_far(kept) && spans_far(removed) {
+        return false;
+    }
+
+    let to_vector = |vertex: u32| Vector3::from(mesh.positions[vertex as usize]).cast::<f64>().unwrap();
+    let normal = |[a, b, c]: [Vector3<f64>; 3]| (b - a).cross(c - a);
+    topology.vertex_faces[removed as usize].iter()
+        .map(|&face| mesh.triangles[face as usize])
+        .filter(|tri| !tri.contains(&kept))
+        .all(|tri| {
+            let before = normal(tri.map(to_vector));
+            let after = normal(tri.map(|vertex| to_vector(if vertex == removed { kept } else { vertex })));
+            before.dot(after) > 0.0
+        })
+}
+
+// A zero-area triangle has one corner lying on its longest edge. Flipping
+// that edge splits the face across it at the corner instead, so the mesh
+// stays closed. Without a face across the edge it's simply removed.
+fn flip_degenerate(mesh: &mut TriMesh) -> usize {
+    let topology = Topology::new(mesh);
+    let mut touched = vec![false; mesh.num_triangles()];
+    let mut keep = vec![true; mesh.num_triangles()];
+    let mut fixed = 0;
+
+    for face in 0..mesh.num_triangles() {
+        if touched[face] || !mesh.is_degenerate(face) {
+            continue;
+        }
+        let lengths = edge_lengths(mesh, face);
+        let longest = (0..3).max_by(|&a, &b| lengths[a].total_cmp(&lengths[b])).unwrap();
+        let tri = mesh.triangles[face];
+        let (a, b, c) = (tri[longest], tri[(longest + 1) % 3], tri[(longest + 2) % 3]);
+
+        let edge = &topology.edges[topology.face_edges[face][longest] as usize];
+        let neighbour = match edge.faces[..] {
+            [first, second] => Some(if first as usize == face { second } else { first } as usize),
+            _ => None,
+        };
+        let neighbour = neighbour.filter(|&neighbour| topology.directed_edge(neighbour as u32, edge.vertices) == [b, a]);
+        match neighbour {
+            Some(neighbour) if touched[neighbour] => continue,
+            Some(neighbour) => {
+                let d = mesh.triangles[neighbour].into_iter().find(|&vertex| vertex != a && vertex != b).unwrap();
+                if d == c {
+                    // The same flat triangle both ways round, nothing to split
+                    keep[face] = false;
+                    keep[neighbour] = false;
+                } else {
+                    mesh.triangles[face] = [d, b, c];
+                    mesh.triangles[neighbour] = [a, d, c];
+                    if let Some(face_colors) = mesh.face_colors.as_mut() {
+                        face_colors[face] = face_colors[neighbour];
+                    }
+                }
+                touched[neighbour] = true;
+            },
+            None => keep[face] = false,
+        }
+        touched[face] = true;
+        fixed += 1;
+    }
+
+    retain_faces(mesh, &keep);
+    fixed
+}
+
+// Faces over the same three vertices collapse to one copy. Where the rest
+// of the mesh is open across an edge of theirs, the copy that closes it
+// consistently is kept. Otherwise the rest is closed without them, so any
+// wound opposite ways pair off as internal walls and one copy of the way
+// most of the others are wound is kept.
+fn remove_duplicates(mesh: &mut TriMesh, report: &mut CleanupReport) {
+    let mut faces_by_vertices: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (face, tri) in mesh.triangles.iter().enumerate() {
+        let mut key = *tri;
+        key.sort_unstable();
+        faces_by_vertices.entry(key).or_default().push(face);
+    }
+
+    let topology = Topology::new(mesh);
+    let mut keep = vec![true; mesh.num_triangles()];
+    for faces in faces_by_vertices.into_values().filter(|faces| faces.len() > 1) {
+        let first = mesh.triangles[faces[0]];
+        let (same, opposite): (Vec<usize>, Vec<usize>) = faces.iter()
+            .partition(|&&face| same_winding(mesh.triangles[face], first));
+
+        let closing = topology.face_edges[faces[0]].iter()
+            .map(|&edge| &topology.edges[edge as usize])
+            .find(|edge| (edge.faces.len() - faces.len()) % 2 == 1)
+            .and_then(|edge| {
+                let outside = *edge.faces.iter().find(|&&face| !faces.contains(&(face as usize)))?;
+                let outside = topology.directed_edge(outside, edge.vertices);
+                faces.iter().copied().find(|&face| topology.directed_edge(face as u32, edge.vertices) != outside)
+            });
+        let survivor = match closing {
+            Some(face) => {
+                report.duplicate_faces += faces.len() - 1;
+                Some(face)
+            },
+            None => {
+                let walls = same.len().min(opposite.len());
+                let majority = if same.len() >= opposite.len() { same } else { opposite };
+                report.internal_walls += walls;
+                report.duplicate_faces += (majority.len() - walls).saturating_sub(1);
+                (majority.len() > walls).then_some(majority[0])
+            },
+        };
+        for &face in &faces {
+            keep[face] = Some(face) == survivor;
+        }
+    }
+
+    retain_faces(mesh, &keep);
+}
+
+fn same_winding(a: [u32; 3], b: [u32; 3]) -> bool {
+    (0..3).any(|i| [b[i], b[(i + 1) % 3], b[(i + 2) % 3]] == a)
+}
+
+fn retain_faces(mesh: &mut TriMesh, keep: &[bool]) {
+    let mut kept = keep.iter();
+    mesh.triangles.retain(|_| *kept.next().unwrap());
+    if let Some(face_colors) = mesh.face_colors.as_mut() {
+        let mut kept = keep.iter();
+        face_colors.retain(|_| *kept.next().unwrap());
+    }
+}
+
+fn remove_unreferenced(mesh: &mut TriMesh) -> usize {
+    let mut compact: Vec<Option<u32>> = vec![None; mesh.positions.len()];
+    let mut positions = Vec::new();
+    let mut colors = mesh.colors.as_ref().map(|_| Vec::new());
+    for tri in &mut mesh.triangles {
+        *tri = tri.map(|index| {
+            *compact[index as usize].get_or_insert_with(|| {
+                positions.push(mesh.positions[index as usize]);
+                if let (Some(colors), Some(source)) = (colors.as_mut(), mesh.colors.as_ref()) {
+                    colors.push(source[index as usize]);
+                }
+                positions.len() as u32 - 1
+            })
+        });
+    }
+
+    let removed = mesh.positions.len() - positions.len();
+    mesh.positions = positions;
+    mesh.colors = colors;
+    removed
+}
+
+impl fmt::Display for CleanupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "removed {} degenerate and {} needle triangles, {} duplicate faces, {} internal walls and {} unreferenced vertices",
+            self.degenerate_triangles,
+            self.needle_triangles,
+            self.duplicate_faces,
+            self.internal_walls,
+            self.unreferenced_vertices
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    fn closed(mesh: &TriMesh) -> bool {
+        let topology = Topology::new(mesh);
+        topology.edges.iter().all(|edge| edge.faces.len() == 2)
+    }
+
+    // Faces by their corners, since cleaning renumbers the vertices
+    fn corners(mesh: &TriMesh) -> Vec<[[f32; 3]; 3]> {
+        mesh.triangles.iter().map(|tri| tri.map(|v| mesh.positions[v as usize])).collect()
+    }
+
+    // The unit cube with the two faces on its edge from corner 0 to 1 split
+    // at a vertex a hair away from corner 0, which makes two needles
+    fn cube_with_needles() -> TriMesh {
+        let mut mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        mesh.positions.push([1e-7, 0.0, 0.0]);
+        mesh.triangles.retain(|&tri| tri != [0, 2, 1] && tri != [0, 1, 4]);
+        mesh.triangles.extend([[0, 2, 8], [8, 2, 1], [0, 8, 4], [8, 1, 4]]);
+        mesh
+    }
+
+    #[test]
+    fn collapses_needles() {
+        let mesh = cube_with_needles();
+        assert!(closed(&mesh));
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!(report.needle_triangles, 1);
+        assert_eq!(report.unreferenced_vertices, 1);
+        assert_eq!(cleaned.num_triangles(), 12);
+        assert!(closed(&cleaned));
+        assert!((cleaned.signed_volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn leaves_thin_strips_alone() {
+        // A long cylinder divided finely enough for its sides to be strips
+        // some 1600 times longer than they're wide
+        let (segments, radius, length) = (1000, 10.0f32, 100.0f32);
+        let mut mesh = TriMesh::new("cylinder");
+        for z in [0.0, length] {
+            mesh.positions.extend((0..segments).map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / segments as f32;
+                [radius * angle.cos(), radius * angle.sin(), z]
+            }));
+        }
+        mesh.positions.extend([[0.0, 0.0, 0.0], [0.0, 0.0, length]]);
+        let (bottom, top) = (2 * segments, 2 * segments + 1);
+        for i in 0..segments {
+            let next = (i + 1) % segments;
+            mesh.triangles.extend([
+                [i, next, next + segments],
+                [i, next + segments, i + segments],
+                [bottom, next, i],
+                [top, i + segments, next + segments],
+            ]);
+        }
+        assert!(closed(&mesh));
+
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!((report.needle_triangles, report.degenerate_triangles, report.duplicate_faces), (0, 0, 0));
+        assert_eq!(corners(&cleaned), corners(&mesh));
+    }
+
+    #[test]
+    fn refuses_collapses_that_pinch_the_surface() {
+        // Collapsing the short edge of a tetrahedron would flatten it into
+        // two faces on top of each other
+        let mut mesh = TriMesh::new("tetrahedron");
+        mesh.positions = vec![[0.0, 0.0, 0.0], [1e-7, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        mesh.triangles = vec![[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!(report.needle_triangles, 0);
+        assert_eq!(corners(&cleaned), corners(&mesh));
+    }
+
+    #[test]
+    fn refuses_collapses_that_turn_faces_around() {
+        // Moving vertex 1 onto vertex 0 takes it across the line between
+        // vertices 3 and 4, which would turn the last face over
+        let mut mesh = TriMesh::new("fold");
+        mesh.positions = vec![[0.0, 0.0, 0.0], [1e-7, 0.0, 0.0], [0.5, 1.0, 0.0], [0.6e-7, 1.0, 0.0], [0.6e-7, -1.0, 0.0]];
+        mesh.triangles = vec![[0, 1, 2], [1, 3, 4]];
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!(report.needle_triangles, 0);
+        assert_eq!(cleaned.num_triangles(), 2);
+    }
+
+    #[test]
+    fn flips_zero_area_triangles_into_their_neighbour() {
+        // The bottom of the cube split at the middle of its diagonal, with a
+        // flat triangle along the diagonal to keep it closed
+        let mut mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        mesh.positions.push([0.5, 0.5, 0.0]);
+        mesh.triangles.retain(|&tri| tri != [0, 2, 1]);
+        mesh.triangles.extend([[0, 2, 8], [0, 8, 1], [2, 1, 8]]);
+        assert!(closed(&mesh));
+
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!(report.degenerate_triangles, 1);
+        assert_eq!(cleaned.num_triangles(), 14);
+        assert!((0..cleaned.num_triangles()).all(|face| !cleaned.is_degenerate(face)));
+        assert!(closed(&cleaned));
+        assert!((cleaned.signed_volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn removes_duplicate_faces() {
+        let mut mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        mesh.triangles.push(mesh.triangles[3]);
+        mesh.triangles.push([2, 3, 1]);
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!((report.duplicate_faces, report.internal_walls), (2, 0));
+        assert_eq!(cleaned.num_triangles(), 12);
+        assert!(closed(&cleaned));
+    }
+
+    #[test]
+    fn removes_internal_walls() {
+        // Two cubes welded together face to face
+        let mut mesh = cube("pair", [0.0; 3], [1.0; 3]);
+        let other = cube("pair", [1.0, 0.0, 0.0], [2.0, 1.0, 1.0]);
+        let shared = [(0, 1), (2, 3), (4, 5), (6, 7)];
+        let offset = mesh.positions.len() as u32;
+        mesh.positions.extend([1, 3, 5, 7].map(|corner| other.positions[corner]));
+        let vertex = |corner: u32| match shared.iter().find(|&&(theirs, _)| theirs == corner) {
+            Some(&(_, ours)) => ours,
+            None => offset + corner / 2,
+        };
+        mesh.triangles.extend(other.triangles.iter().map(|tri| tri.map(vertex)));
+
+        let (cleaned, report) = clean(&mesh);
+        assert_eq!((report.internal_walls, report.duplicate_faces), (2, 0));
+        assert_eq!(cleaned.num_triangles(), 20);
+        assert!(closed(&cleaned));
+        assert!((cleaned.signed_volume() - 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+// Exact geometry for the mesh arrangement. Input positions are f32 and so
+// exactly representable as f64, which lets predicates on them go through
+// Shewchuk's adaptive precision tests. Points constructed from them are
+// kept as rationals, so every face meeting at one agrees on exactly where
+// it is and the pieces fit back together without cracks. None of it
+// copes with NaN or infinity, so input has to be checked to be finite
+// before it gets here.
+
+pub type Exact = [BigRational; 3];
+
+// Relative error the f64 shadow of an exact point may carry, with plenty of
+// room to spare, before a predicate falls back to rationals
+const FILTER_EPSILON: f64 = 1e-12;
+
+#[derive(Clone, Debug)]
+pub struct ExactPoint {
+    pub exact: Exact,
+    // Nearest f64 position, for filtering predicates and for output
+    pub approx: [f64; 3],
+}
+
+impl ExactPoint {
+    pub fn new(exact: Exact) -> Self {
+        let approx = [0, 1, 2].map(|i| exact[i].to_f64().unwrap_or(f64::NAN));
+        Self { exact, approx }
+    }
+
+    pub fn from_f32(position: [f32; 3]) -> Self {
+        Self {
+            exact: position.map(|v| BigRational::from_float(v).expect("positions are checked to be finite")),
+            approx: position.map(|v| v as f64),
+        }
+    }
+
+    pub fn to_f32(&self) -> [f32; 3] {
+        self.approx.map(|v| v as f32)
+    }
+}
+
+pub fn sub(a: &Exact, b: &Exact) -> Exact {
+    [0, 1, 2].map(|i| &a[i] - &b[i])
+}
+
+pub fn add(a: &Exact, b: &Exact) -> Exact {
+    [0, 1, 2].map(|i| &a[i] + &b[i])
+}
+
+pub fn scale(a: &Exact, factor: &BigRational) -> Exact {
+    [0, 1, 2].map(|i| &a[i] * factor)
+}
+
+pub fn dot(a: &Exact, b: &Exact) -> BigRational {
+    &a[0] * &b[0] + &a[1] * &b[1] + &a[2] * &b[2]
+}
+
+pub fn cross(a: &Exact, b: &Exact) -> Exact {
+    [
+        &a[1] * &b[2] - &a[2] * &b[1],
+        &a[2] * &b[0] - &a[0] * &b[2],
+        &a[0] * &b[1] - &a[1] * &b[0],
+    ]
+}
+
+// Normal of the triangle's winding, not normalized
+pub fn normal([a, b, c]: &[ExactPoint; 3]) -> Exact {
+    cross(&sub(&b.exact, &a.exact), &sub(&c.exact, &a.exact))
+}
+
+// Which side of the plane through a, b and c an input position lies on,
+// Greater being the side their winding's normal points to
+pub fn side_of_plane([a, b, c]: [[f32; 3]; 3], point: [f32; 3]) -> Ordering {
+    let coord = |p: [f32; 3]| robust::Coord3D { x: p[0], y: p[1], z: p[2] };
+    // Shewchuk's orient3d is positive below the plane, against the normal
+    0.0.partial_cmp(&robust::orient3d(coord(a), coord(b), coord(c), coord(point))).unwrap()
+}
+
+// Orientation of three input positions projected onto two axes
+pub fn orient2d_f32(a: [f32; 3], b: [f32; 3], c: [f32; 3], [i, j]: [usize; 2]) -> Ordering {
+    let coord = |p: [f32; 3]| robust::Coord { x: p[i], y: p[j] };
+    robust::orient2d(coord(a), coord(b), coord(c)).partial_cmp(&0.0).unwrap()
+}
+
+// Orientation of three points projected onto two axes, Greater when they
+// turn counterclockwise. Tries the f64 shadows first.
+pub fn orient2d(a: &ExactPoint, b: &ExactPoint, c: &ExactPoint, [i, j]: [usize; 2]) -> Ordering {
+    let (ax, ay, bx, by, cx, cy) = (a.approx[i], a.approx[j], b.approx[i], b.approx[j], c.approx[i], c.approx[j]);
+    let determinant = (bx - ax) * (cy - ay) - (by - ay) * (cx - ax);
+    let bound = FILTER_EPSILON
+        * ((ax.abs() + bx.abs()) * (ay.abs() + cy.abs()) + (ay.abs() + by.abs()) * (ax.abs() + cx.abs()));
+    if determinant.abs() > bound {
+        return determinant.partial_cmp(&0.0).unwrap();
+    }
+
+    let (a, b, c) = (&a.exact, &b.exact, &c.exact);
+    let determinant = (&b[i] - &a[i]) * (&c[j] - &a[j]) - (&b[j] - &a[j]) * (&c[i] - &a[i]);
+    determinant.cmp(&BigRational::zero())
+}
+
+// Where the segment from p to q crosses the plane through the triangle,
+// for a segment whose ends are known to lie on opposite sides of it
+pub fn segment_plane(p: [f32; 3], q: [f32; 3], plane: [[f32; 3]; 3]) -> ExactPoint {
+    let [p, q] = [p, q].map(ExactPoint::from_f32);
+    let plane = plane.map(ExactPoint::from_f32);
+    let direction = sub(&q.exact, &p.exact);
+    let normal = normal(&plane);
+    let t = dot(&normal, &sub(&plane[0].exact, &p.exact)) / dot(&normal, &direction);
+    ExactPoint::new(add(&p.exact, &scale(&direction, &t)))
+}
+
+// Where the lines through p1, p2 and p3, p4 meet, for coplanar lines known
+// to cross. Working in a projection is exact as long as the plane isn't
+// seen edge on along the axes.
+pub fn line_intersection(p1: &ExactPoint, p2: &ExactPoint, p3: &ExactPoint, p4: &ExactPoint, axes: [usize; 2]) -> ExactPoint {
+    let first = sub(&p2.exact, &p1.exact);
+    let second = sub(&p4.exact, &p3.exact);
+    let offset = sub(&p3.exact, &p1.exact);
+    let t = cross2(&offset, &second, axes) / cross2(&first, &second, axes);
+    ExactPoint::new(add(&p1.exact, &scale(&first, &t)))
+}
+
+// The part of the segment from p to q inside a coplanar triangle, if any.
+// Both ends are the same point when the segment only touches a corner.
+pub fn clip_segment(p: &ExactPoint, q: &ExactPoint, triangle: &[ExactPoint; 3], axes: [usize; 2]) -> Option<[ExactPoint; 2]> {
+    let winding = cross2(
+        &sub(&triangle[1].exact, &triangle[0].exact),
+        &sub(&triangle[2].exact, &triangle[0].exact),
+        axes,
+    );
+    let direction = sub(&q.exact, &p.exact);
+    let (mut enter, mut exit) = (BigRational::zero(), BigRational::one());
+    for i in 0..3 {
+        let (a, b) = (&triangle[i], &triangle[(i + 1) % 3]);
+        let edge = sub(&b.exact, &a.exact);
+        // How far inside the edge either end is, in units of the winding
+        let inside = |point: &ExactPoint| cross2(&edge, &sub(&point.exact, &a.exact), axes) * &winding;
+        let (from, to) = (inside(p), inside(q));
+        match (from.is_negative(), to.is_negative()) {
+            (true, true) => return None,
+            (true, false) => enter = enter.max(&from / (&from - &to)),
+            (false, true) => exit = exit.min(&from / (&from - &to)),
+            (false, false) => {},
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+
+    let at = |t: &BigRational| ExactPoint::new(add(&p.exact, &scale(&direction, t)));
+    Some([at(&enter), at(&exit)])
+}
+
+fn cross2(u: &Exact, v: &Exact, [i, j]: [usize; 2]) -> BigRational {
+    &u[i] * &v[j] - &u[j] * &v[i]
+}
+
+// The two axes to project a plane with this normal onto, ordered so
+// counterclockwise in the projection means the same winding as the normal
+pub fn projection_axes(normal: [f64; 3]) -> [usize; 2] {
+    let dropped = (0..3).max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs())).unwrap();
+    let axes = [(dropped + 1) % 3, (dropped + 2) % 3];
+    if normal[dropped] < 0.0 {
+        [axes[1], axes[0]]
+    } else {
+        axes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(numerator: i64, denominator: i64) -> BigRational {
+        BigRational::new(numerator.into(), denominator.into())
+    }
+
+    #[test]
+    fn constructed_points_lie_exactly_on_their_lines() {
+        // The lines meet at (1/3, 2/3), which no float can hold
+        let [p1, p2, p3, p4] = [[0.0, 0.0, 0.0], [1.0, 2.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]].map(ExactPoint::from_f32);
+        let point = line_intersection(&p1, &p2, &p3, &p4, [0, 1]);
+        assert_eq!(point.exact, [rational(1, 3), rational(2, 3), rational(0, 1)]);
+        assert_eq!(orient2d(&p1, &p2, &point, [0, 1]), Ordering::Equal);
+        assert_eq!(orient2d(&p3, &p4, &point, [0, 1]), Ordering::Equal);
+        // While its nearest float is off the line
+        let rounded = ExactPoint::from_f32(point.to_f32());
+        assert_ne!(orient2d(&p3, &p4, &rounded, [0, 1]), Ordering::Equal);
+    }
+
+    #[test]
+    fn finds_where_segments_cross_planes() {
+        let plane = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let point = segment_plane([1.0, 0.0, -1.0], [0.0, 3.0, 2.0], plane);
+        assert_eq!(point.exact, [rational(2, 3), rational(1, 1), rational(0, 1)]);
+        assert_eq!(side_of_plane(plane, [5.0, 5.0, 1e-30]), Ordering::Greater);
+        assert_eq!(side_of_plane(plane, [5.0, 5.0, -1e-30]), Ordering::Less);
+        assert_eq!(side_of_plane(plane, [5.0, -5.0, 0.0]), Ordering::Equal);
+    }
+
+    #[test]
+    fn clips_segments_to_triangles() {
+        let triangle = [[0.0, 0.0, 0.0], [4.0, 0.0, 0.0], [0.0, 4.0, 0.0]].map(ExactPoint::from_f32);
+        let clip = |p: [f32; 3], q: [f32; 3]| {
+            clip_segment(&ExactPoint::from_f32(p), &ExactPoint::from_f32(q), &triangle, [0, 1]).map(|ends| ends.map(|end| end.to_f32()))
+        };
+        assert_eq!(clip([-1.0, 1.0, 0.0], [5.0, 1.0, 0.0]), Some([[0.0, 1.0, 0.0], [3.0, 1.0, 0.0]]));
+        assert_eq!(clip([1.0, 1.0, 0.0], [2.0, 1.0, 0.0]), Some([[1.0, 1.0, 0.0], [2.0, 1.0, 0.0]]));
+        assert_eq!(clip([4.0, 4.0, 0.0], [5.0, 1.0, 0.0]), None);
+        // Only touching a corner
+        assert_eq!(clip([4.0, -1.0, 0.0], [4.0, 1.0, 0.0]), Some([[4.0, 0.0, 0.0]; 2]));
+    }
+
+    #[test]
+    fn projects_keeping_the_winding() {
+        let [a, b, c] = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]];
+        // Wound clockwise seen from above, so its normal points down
+        let axes = projection_axes([0.0, 0.0, -1.0]);
+        assert_eq!(axes, [1, 0]);
+        assert_eq!(orient2d_f32(a, b, c, axes), Ordering::Greater);
+        assert_eq!(projection_axes([0.1, 2.0, -0.5]), [2, 0]);
+    }
+}
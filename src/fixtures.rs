@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use crate::mesh::TriMesh;
+
+// A path in the temp directory unique to this test run, for readers and
+// writers that only work on files
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("solidify_{}_{}", std::process::id(), name))
+}
+
+// Welded, outward wound axis aligned box from min to max
+pub fn cube(name: &str, min: [f32; 3], max: [f32; 3]) -> TriMesh {
+    let mut mesh = TriMesh::new(name);
+    mesh.positions = (0..8)
+        .map(|corner| [0, 1, 2].map(|axis| if corner & (1 << axis) != 0 { max[axis] } else { min[axis] }))
+        .collect();
+    mesh.triangles = vec![
+        [0, 2, 1], [1, 2, 3],
+        [4, 5, 6], [5, 7, 6],
+        [0, 1, 4], [1, 5, 4],
+        [2, 6, 3], [3, 6, 7],
+        [0, 4, 2], [2, 4, 6],
+        [1, 3, 5], [3, 7, 5],
+    ];
+    mesh
+}
+
+
+// The same mesh with every face turned around
+pub fn inverted(mut mesh: TriMesh) -> TriMesh {
+    for tri in &mut mesh.triangles {
+        tri.swap(1, 2);
+    }
+    mesh
+}
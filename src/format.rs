@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::mesh::Scene;
+use crate::obj::{is_obj, load_obj, save_obj, ObjError};
+use crate::ply::{is_ply, load_ply, save_ply, PlyError, PlyFormat};
+use crate::stl::{is_stl, load_stl, save_stl, StlError};
+use crate::threemf::{is_3mf, load_3mf, save_3mf, ThreeMfError};
+
+// Enough of the start of a file for every format's sniff
+const SNIFF_SIZE: u64 = 512;
+
+#[derive(Debug)]
+pub enum FormatError {
+    NotFound(String),
+    UnknownFormat(String),
+    // The format is known but has no writer
+    ReadOnly { format: &'static str },
+    Stl(StlError),
+    Obj(ObjError),
+    ThreeMf(ThreeMfError),
+    Ply(PlyError),
+    // Errors from formats registered outside this crate
+    Other(Box<dyn std::error::Error + Send + Sync>),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::NotFound(path) => write!(f, "{} does not exist", path),
+            FormatError::UnknownFormat(path) => write!(f, "{} is not in a recognised mesh format", path),
+            FormatError::ReadOnly { format } => write!(f, "{} files can only be read", format),
+            FormatError::Stl(e) => write!(f, "{}", e),
+            FormatError::Obj(e) => write!(f, "{}", e),
+            FormatError::ThreeMf(e) => write!(f, "{}", e),
+            FormatError::Ply(e) => write!(f, "{}", e),
+            FormatError::Other(e) => write!(f, "{}", e),
+            FormatError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Stl(e) => Some(e),
+            FormatError::Obj(e) => Some(e),
+            FormatError::ThreeMf(e) => Some(e),
+            FormatError::Ply(e) => Some(e),
+            FormatError::Other(e) => Some(e.as_ref()),
+            FormatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(e: std::io::Error) -> Self {
+        FormatError::Io(e)
+    }
+}
+
+impl From<StlError> for FormatError {
+    fn from(e: StlError) -> Self {
+        FormatError::Stl(e)
+    }
+}
+
+impl From<ObjError> for FormatError {
+    fn from(e: ObjError) -> Self {
+        FormatError::Obj(e)
+    }
+}
+
+impl From<ThreeMfError> for FormatError {
+    fn from(e: ThreeMfError) -> Self {
+        FormatError::ThreeMf(e)
+    }
+}
+
+impl From<PlyError> for FormatError {
+    fn from(e: PlyError) -> Self {
+        FormatError::Ply(e)
+    }
+}
+
+pub type LoadFn = fn(&Path) -> Result<Scene, FormatError>;
+pub type SaveFn = fn(&Scene, &Path) -> Result<(), FormatError>;
+
+// Everything the registry needs to know about one file format
+#[derive(Clone, Copy)]
+pub struct FileFormat {
+    pub name: &'static str,
+    // Lowercase, without the dot
+    pub extensions: &'static [&'static str],
+    // Whether the first few hundred bytes of a file (and its total length)
+    // look like this format
+    pub sniff: fn(&[u8], u64) -> bool,
+    pub load: LoadFn,
+    pub save: Option<SaveFn>,
+}
+
+pub struct Registry {
+    formats: Vec<FileFormat>,
+}
+
+// Formats with a real magic number come first so they win over the
+// looser text sniffs
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            formats: vec![
+                FileFormat {
+                    name: "PLY",
+                    extensions: &["ply"],
+                    sniff: |sample, _| is_ply(sample),
+                    load: |path| Ok(load_ply(path)?),
+                    save: Some(|scene, path| Ok(save_ply(scene, path, PlyFormat::BinaryLittleEndian)?)),
+                },
+                FileFormat {
+                    name: "3MF",
+                    extensions: &["3mf"],
+                    sniff: |sample, _| is_3mf(sample),
+                    load: |path| Ok(load_3mf(path)?),
+                    save: Some(|scene, path| Ok(save_3mf(scene, path)?)),
+                },
+                FileFormat {
+                    name: "STL",
+                    extensions: &["stl"],
+                    sniff: is_stl,
+                    load: |path| Ok(load_stl(path)?),
+                    save: Some(|scene, path| Ok(save_stl(scene, path)?)),
+                },
+                FileFormat {
+                    name: "OBJ",
+                    extensions: &["obj"],
+                    sniff: |sample, _| is_obj(sample),
+                    load: |path| Ok(load_obj(path)?),
+                    save: Some(|scene, path| Ok(save_obj(scene, path)?)),
+                },
+            ],
+        }
+    }
+}
+
+impl Registry {
+    pub fn empty() -> Self {
+        Self { formats: Vec::new() }
+    }
+
+    // Later registrations take priority over earlier ones for both
+    // extension lookup and content sniffing
+    pub fn register(&mut self, format: FileFormat) {
+        self.formats.insert(0, format);
+    }
+
+    pub fn formats(&self) -> &[FileFormat] {
+        &self.formats
+    }
+
+    pub fn by_extension(&self, path: &Path) -> Option<&FileFormat> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        self.formats.iter().find(|format| format.extensions.contains(&extension.as_str()))
+    }
+
+    // The extension wins when the contents agree with it. Otherwise the
+    // contents decide, so a binary STL saved as .obj still loads, and the
+    // extension is the last resort for files that don't sniff as anything.
+    pub fn detect(&self, path: &Path) -> Result<&FileFormat, FormatError> {
+        let mut file = File::open(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => FormatError::NotFound(path.display().to_string()),
+            _ => FormatError::Io(e),
+        })?;
+        let file_len = file.metadata()?.len();
+        let mut sample = Vec::with_capacity(SNIFF_SIZE as usize);
+        file.by_ref().take(SNIFF_SIZE).read_to_end(&mut sample)?;
+
+        let by_extension = self.by_extension(path);
+        if let Some(format) = by_extension.filter(|format| (format.sniff)(&sample, file_len)) {
+            return Ok(format);
+        }
+        self.formats.iter()
+            .find(|format| (format.sniff)(&sample, file_len))
+            .or(by_extension)
+            .ok_or_else(|| FormatError::UnknownFormat(path.display().to_string()))
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Scene, FormatError> {
+        let path = path.as_ref();
+        let format = self.detect(path)?;
+        log::debug!("loading {} as {}", path.display(), format.name);
+        (format.load)(path)
+    }
+
+    // Picks the format from the extension, there's nothing to sniff yet
+    pub fn save(&self, scene: &Scene, path: impl AsRef<Path>) -> Result<(), FormatError> {
+        let path = path.as_ref();
+        let format = self.by_extension(path)
+            .ok_or_else(|| FormatError::UnknownFormat(path.display().to_string()))?;
+        let save = format.save.ok_or(FormatError::ReadOnly { format: format.name })?;
+        save(scene, path)
+    }
+}
+
+// Loads any of the built in formats
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, FormatError> {
+    Registry::default().load(path)
+}
+
+pub fn save(scene: &Scene, path: impl AsRef<Path>) -> Result<(), FormatError> {
+    Registry::default().save(scene, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+
+    fn cube_scene(header: &[u8]) -> Scene {
+        Scene { header: header.to_vec(), meshes: vec![cube("cube", [0.0; 3], [10.0; 3])] }
+    }
+
+    fn detected(path: &Path) -> &'static str {
+        Registry::default().detect(path).unwrap().name
+    }
+
+    #[test]
+    fn round_trips_every_writable_format() {
+        let registry = Registry::default();
+        for format in registry.formats().iter().filter(|format| format.save.is_some()) {
+            let path = temp_path(&format!("round_trip.{}", format.extensions[0]));
+            registry.save(&cube_scene(b""), &path).unwrap();
+            assert_eq!(detected(&path), format.name);
+
+            let scene = registry.load(&path).unwrap();
+            assert_eq!(scene.num_triangles(), 12, "{}", format.name);
+            let volume: f64 = scene.meshes.iter().map(|mesh| mesh.signed_volume()).sum();
+            assert!((volume - 1000.0).abs() < 1e-6, "{} volume {}", format.name, volume);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn contents_win_over_a_wrong_extension() {
+        let stl = temp_path("misnamed.stl");
+        save(&cube_scene(b""), &stl).unwrap();
+        let obj = temp_path("misnamed.obj");
+        std::fs::copy(&stl, &obj).unwrap();
+        assert_eq!(detected(&obj), "STL");
+        assert_eq!(load(&obj).unwrap().num_triangles(), 12);
+
+        // A binary STL whose header happens to start like an ASCII one
+        let solid = temp_path("solid_header.stl");
+        save(&cube_scene(b"solid exported by something"), &solid).unwrap();
+        assert!(std::fs::read(&solid).unwrap().starts_with(b"solid"));
+        assert_eq!(detected(&solid), "STL");
+        assert_eq!(load(&solid).unwrap().num_triangles(), 12);
+
+        for path in [stl, obj, solid] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn extensions_are_case_insensitive() {
+        for (extension, name) in [("STL", "STL"), ("Obj", "OBJ"), ("PLY", "PLY"), ("3MF", "3MF")] {
+            let path = temp_path(&format!("upper.{}", extension));
+            save(&cube_scene(b""), &path).unwrap();
+            assert_eq!(detected(&path), name);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn reports_unknown_and_missing_files() {
+        let path = temp_path("unknown.xyz");
+        std::fs::write(&path, b"\x00\x01 definitely not a mesh").unwrap();
+        assert!(matches!(load(&path), Err(FormatError::UnknownFormat(_))));
+        assert!(matches!(save(&cube_scene(b""), &path), Err(FormatError::UnknownFormat(_))));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(load(&path), Err(FormatError::NotFound(_))));
+    }
+
+    #[test]
+    fn refuses_to_save_read_only_formats() {
+        let mut registry = Registry::default();
+        registry.register(FileFormat {
+            name: "XYZ",
+            extensions: &["xyz"],
+            sniff: |sample, _| sample.starts_with(b"xyz"),
+            load: |_| Ok(Scene { header: Vec::new(), meshes: Vec::new() }),
+            save: None,
+        });
+        let path = temp_path("read_only.xyz");
+        assert!(matches!(registry.save(&cube_scene(b""), &path), Err(FormatError::ReadOnly { format: "XYZ" })));
+        assert!(!path.exists());
+
+        // Registered formats sniff like the built in ones
+        std::fs::write(&path, b"xyz points").unwrap();
+        assert_eq!(registry.detect(&path).unwrap().name, "XYZ");
+        std::fs::remove_file(path).unwrap();
+    }
+}
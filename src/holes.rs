@@ -0,0 +1,284 @@
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+
+use crate::mesh::{polygon_normal, triangle_area, triangulate_polygon, TriMesh};
+use crate::topology::Topology;
+use crate::validate::boundary_loops;
+
+// Loops up to this many edges get a fan if one fits
+const FAN_MAX_EDGES: usize = 8;
+// How far off its plane a loop can stray, relative to its perimeter,
+// and still count as flat enough for a fan
+const PLANAR_TOLERANCE: f64 = 0.01;
+// Loops up to this many edges get the least area patch. Working it out
+// takes memory growing with the square of the edges and time with the
+// cube, so longer loops are ear clipped instead.
+const MINIMUM_AREA_MAX_EDGES: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HoleLimit {
+    // Holes with more boundary edges than this are left open
+    pub max_edges: usize,
+    // And so are holes longer around than this, in file units
+    pub max_perimeter: Option<f64>,
+}
+
+impl Default for HoleLimit {
+    fn default() -> Self {
+        Self { max_edges: 64, max_perimeter: None }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FillMethod {
+    Fan,
+    MinimumArea,
+    EarClipping,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    TooManyEdges,
+    TooLong,
+    // The boundary runs through a non-manifold vertex and doesn't form a loop
+    Unclosed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FilledHole {
+    pub edges: usize,
+    pub perimeter: f64,
+    pub method: FillMethod,
+    pub triangles: usize,
+    pub area: f64,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SkippedHole {
+    pub edges: usize,
+    pub perimeter: f64,
+    pub reason: SkipReason,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HoleReport {
+    pub filled: Vec<FilledHole>,
+    pub skipped: Vec<SkippedHole>,
+}
+
+impl HoleReport {
+    pub fn added_area(&self) -> f64 {
+        self.filled.iter().map(|hole| hole.area).sum()
+    }
+}
+
+// Closes the boundary loops of a welded mesh with new triangles between
+// the existing boundary vertices. Patches are wound to match the faces
+// around them, so a consistently oriented mesh stays that way.
+pub fn fill_holes(mesh: &TriMesh, limit: HoleLimit) -> (TriMesh, HoleReport) {
+    let topology = Topology::new(mesh);
+    let mut filled = mesh.clone();
+    let mut report = HoleReport::default();
+
+    for hole in boundary_loops(mesh, &topology) {
+        let edges = if hole.closed { hole.vertices.len() } else { hole.vertices.len() - 1 };
+        let reason = if !hole.closed {
+            Some(SkipReason::Unclosed)
+        } else if edges > limit.max_edges {
+            Some(SkipReason::TooManyEdges)
+        } else if limit.max_perimeter.is_some_and(|max_perimeter| hole.length > max_perimeter) {
+            Some(SkipReason::TooLong)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            report.skipped.push(SkippedHole { edges, perimeter: hole.length, reason, location: hole.location });
+            continue;
+        }
+
+        let points: Vec<[f32; 3]> = hole.vertices.iter().map(|&vertex| mesh.positions[vertex as usize]).collect();
+        let (method, patch) = match fan(&points, hole.length) {
+            Some(patch) => (FillMethod::Fan, patch),
+            None if points.len() <= MINIMUM_AREA_MAX_EDGES => (FillMethod::MinimumArea, minimum_area(&points)),
+            None => (FillMethod::EarClipping, triangulate_polygon(&points)),
+        };
+
+        // The loop runs the same way as the faces around it, so the patch
+        // has to run the other way to share edges with them properly
+        let color = neighbour_face(&topology, hole.vertices[0], hole.vertices[1])
+            .zip(mesh.face_colors.as_ref())
+            .and_then(|(face, colors)| colors[face as usize]);
+        let mut area = 0.0;
+        for [a, b, c] in &patch {
+            area += triangle_area([points[*a], points[*b], points[*c]]);
+            filled.triangles.push([hole.vertices[*c], hole.vertices[*b], hole.vertices[*a]]);
+            if let Some(face_colors) = filled.face_colors.as_mut() {
+                face_colors.push(color);
+            }
+        }
+        report.filled.push(FilledHole {
+            edges,
+            perimeter: hole.length,
+            method,
+            triangles: patch.len(),
+            area,
+            location: hole.location,
+        });
+    }
+
+    (filled, report)
+}
+
+fn neighbour_face(topology: &Topology, a: u32, b: u32) -> Option<u32> {
+    topology.vertex_faces[a as usize].iter()
+        .copied()
+        .find(|&face| topology.triangles[face as usize].contains(&b))
+}
+
+// A fan from the first vertex, if the loop is small, flat and convex
+// enough from that vertex for every fan triangle to face the same way
+fn fan(points: &[[f32; 3]], perimeter: f64) -> Option<Vec<[usize; 3]>> {
+    if points.len() > FAN_MAX_EDGES {
+        return None;
+    }
+    let normal = polygon_normal(points);
+    if normal.magnitude2() == 0.0 {
+        return None;
+    }
+    let normal = normal.normalize();
+
+    let to_vector = |point: [f32; 3]| Vector3::from(point).cast::<f64>().unwrap();
+    let centre = points.iter().map(|&point| to_vector(point)).sum::<Vector3<f64>>() / points.len() as f64;
+    let flat = points.iter().all(|&point| (to_vector(point) - centre).dot(normal).abs() <= PLANAR_TOLERANCE * perimeter);
+    let convex = (1..points.len() - 1).all(|i| {
+        let origin = to_vector(points[0]);
+        (to_vector(points[i]) - origin).cross(to_vector(points[i + 1]) - origin).dot(normal) > 0.0
+    });
+
+    (flat && convex).then(|| (1..points.len() - 1).map(|i| [0, i, i + 1]).collect())
+}
+
+// Dynamic programming over the loop for the triangulation with the least
+// total area, which is what a smooth patch over a small hole looks like
+fn minimum_area(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return Vec::new();
+    }
+
+    // cost[i][j] is the least area triangulating the sub-polygon i..=j,
+    // split[i][j] the vertex that triangle (i, split, j) uses to get it
+    let mut cost = vec![vec![0.0f64; n]; n];
+    let mut split = vec![vec![0usize; n]; n];
+    for span in 2..n {
+        for i in 0..n - span {
+            let j = i + span;
+            let (best, best_split) = (i + 1..j)
+                .map(|m| (cost[i][m] + cost[m][j] + triangle_area([points[i], points[m], points[j]]), m))
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap();
+            cost[i][j] = best;
+            split[i][j] = best_split;
+        }
+    }
+
+    let mut triangles = Vec::with_capacity(n - 2);
+    let mut pending = vec![(0, n - 1)];
+    while let Some((i, j)) = pending.pop() {
+        if j - i < 2 {
+            continue;
+        }
+        let m = split[i][j];
+        triangles.push([i, m, j]);
+        pending.push((i, m));
+        pending.push((m, j));
+    }
+    triangles
+}
+
+impl fmt::Display for HoleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "filled {} holes adding {:.3} of surface, left {} open",
+            self.filled.len(), self.added_area(), self.skipped.len()
+        )?;
+        for hole in &self.filled {
+            writeln!(
+                f,
+                "  filled {} edge hole at {:?} with {} triangles ({:?}), perimeter {:.3}",
+                hole.edges, hole.location, hole.triangles, hole.method, hole.perimeter
+            )?;
+        }
+        for hole in &self.skipped {
+            writeln!(
+                f,
+                "  left {} edge hole at {:?} open ({:?}), perimeter {:.3}",
+                hole.edges, hole.location, hole.reason, hole.perimeter
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    // A flat disc of triangles around its centre, one hole the size of
+    // the disc when seen from the other side
+    fn disc(edges: usize) -> TriMesh {
+        let mut mesh = TriMesh::new("disc");
+        mesh.positions.push([0.0; 3]);
+        mesh.positions.extend((0..edges).map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / edges as f32;
+            [angle.cos(), angle.sin(), 0.0]
+        }));
+        mesh.triangles = (0..edges as u32).map(|i| [0, i + 1, (i + 1) % edges as u32 + 1]).collect();
+        mesh
+    }
+
+    fn fill(mesh: &TriMesh) -> (TriMesh, HoleReport) {
+        fill_holes(mesh, HoleLimit { max_edges: usize::MAX, max_perimeter: None })
+    }
+
+    #[test]
+    fn closes_a_missing_face_with_a_fan() {
+        let mut mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        mesh.triangles.truncate(10);
+        let (filled, report) = fill(&mesh);
+        assert_eq!(report.filled.len(), 1);
+        assert_eq!(report.filled[0].method, FillMethod::Fan);
+        assert!((report.added_area() - 1.0).abs() < 1e-6);
+        assert!(Topology::new(&filled).boundary_edges().is_empty());
+        assert!((filled.signed_volume() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fills_loops_by_size() {
+        for (edges, method) in [(12, FillMethod::MinimumArea), (MINIMUM_AREA_MAX_EDGES + 1, FillMethod::EarClipping)] {
+            let mesh = disc(edges);
+            let (filled, report) = fill(&mesh);
+            assert_eq!(report.filled.len(), 1);
+            assert_eq!(report.filled[0].method, method);
+            assert_eq!(report.filled[0].triangles, edges - 2);
+            assert!((report.added_area() - mesh.surface_area()).abs() < 1e-4);
+            assert!(Topology::new(&filled).boundary_edges().is_empty());
+        }
+    }
+
+    #[test]
+    fn leaves_holes_over_the_limit_open() {
+        let (filled, report) = fill_holes(&disc(12), HoleLimit { max_edges: 11, max_perimeter: None });
+        assert!(report.filled.is_empty());
+        assert_eq!(report.skipped[0].reason, SkipReason::TooManyEdges);
+        assert_eq!(filled.num_triangles(), 12);
+    }
+}
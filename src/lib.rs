@@ -0,0 +1,31 @@
+pub mod window;
+pub mod surface;
+pub mod vertex;
+pub mod camera;
+pub mod model;
+pub mod mesh;
+pub mod format;
+pub mod exact;
+pub mod stl;
+pub mod obj;
+pub mod orient;
+pub mod ply;
+pub mod threemf;
+pub mod weld;
+pub mod topology;
+pub mod validate;
+pub mod shell;
+pub mod cavity;
+pub mod voxel;
+pub mod cleanup;
+pub mod holes;
+pub mod union;
+pub mod boolean;
+pub mod arrangement;
+pub mod batch;
+pub mod broadphase;
+pub mod report;
+pub mod texture;
+
+#[cfg(test)]
+mod fixtures;
@@ -1,12 +1,300 @@
-mod window;
-mod surface;
-mod vertex;
-mod camera;
-mod model;
-mod texture;
-
-fn main() {
-    println!("Hello, world!");
-    // window::run();
-    pollster::block_on(window::run());
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use solidify::batch::{run_batch, BatchOptions};
+use solidify::boolean::{boolean, BooleanOp};
+use solidify::cavity::{fill_scene, FillMode};
+use solidify::cleanup::clean;
+use solidify::format::{load, save};
+use solidify::holes::{fill_holes, HoleLimit};
+use solidify::mesh::{Scene, TriMesh};
+use solidify::orient::{disagreeing_normals, orient};
+use solidify::report::analyze;
+use solidify::union::self_union;
+use solidify::validate::validate_scene;
+use solidify::voxel::voxel_fill_scene;
+use solidify::weld::weld;
+
+#[derive(Parser)]
+#[command(name = "solidify", version, about = "Removes hollow cavities from STL files for resin printing")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fill enclosed cavities and write a solid STL
+    Fill {
+        input: PathBuf,
+        /// Defaults to <input>_solid.<ext> next to the input, in the input's format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Only fill cavities smaller than this volume (in cubic file units)
+        #[arg(long)]
+        max_volume: Option<f64>,
+        /// Merge vertices closer than this when building connectivity
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+        /// Rebuild the surface from voxels this size (in file units) instead,
+        /// for meshes too broken to find cavities in. Fills every cavity.
+        #[arg(long, conflicts_with = "max_volume")]
+        voxel_size: Option<f32>,
+    },
+    /// Close small holes and fix face orientation so cavities are found reliably
+    Repair {
+        input: PathBuf,
+        /// Defaults to <input>_repaired.<ext> next to the input, in the input's format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave holes with more boundary edges than this open
+        #[arg(long, default_value_t = HoleLimit::default().max_edges)]
+        max_hole_edges: usize,
+        /// Leave holes longer around than this open (in file units)
+        #[arg(long)]
+        max_hole_perimeter: Option<f64>,
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+    },
+    /// Combine two closed meshes into one
+    Boolean {
+        /// union, difference (the second cut out of the first) or intersection
+        operation: BooleanOp,
+        first: PathBuf,
+        /// Defaults to the second of exactly two meshes in the first file
+        second: Option<PathBuf>,
+        /// Defaults to <first>_<operation>.<ext> next to the first input, in its format
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+    },
+    /// Fill every matching file under a directory, mirroring it into another
+    Batch {
+        input_dir: PathBuf,
+        #[arg(short, long)]
+        output_dir: PathBuf,
+        /// Glob patterns for files to process, relative to the input directory
+        #[arg(long, default_value = "*.stl")]
+        include: Vec<glob::Pattern>,
+        /// Glob patterns for files to skip
+        #[arg(long)]
+        exclude: Vec<glob::Pattern>,
+        #[arg(long)]
+        max_volume: Option<f64>,
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+        /// Worker threads, defaults to one per CPU core
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Print mesh statistics and any cavities found
+    Info {
+        input: PathBuf,
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+        /// Print the report as JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that every mesh is watertight and list any defects found
+    Validate {
+        input: PathBuf,
+        #[arg(long, default_value_t = 0.0)]
+        weld_epsilon: f32,
+        /// Print the report as JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Open the model in the viewer, press S to save a filled copy
+    View {
+        input: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Fill { input, output, max_volume, weld_epsilon, voxel_size } => {
+            let output = output.unwrap_or_else(|| solid_path(&input));
+            match voxel_size {
+                Some(voxel_size) => voxel_fill(&input, &output, voxel_size),
+                None => fill(&input, &output, max_volume.map_or(FillMode::All, FillMode::BelowVolume), weld_epsilon),
+            }
+        },
+        Command::Repair { input, output, max_hole_edges, max_hole_perimeter, weld_epsilon } => {
+            let output = output.unwrap_or_else(|| suffixed_path(&input, "repaired"));
+            let limit = HoleLimit { max_edges: max_hole_edges, max_perimeter: max_hole_perimeter };
+            repair(&input, &output, limit, weld_epsilon)
+        },
+        Command::Boolean { operation, first, second, output, weld_epsilon } => {
+            let output = output.unwrap_or_else(|| suffixed_path(&first, &operation.to_string()));
+            combine(operation, &first, second.as_deref(), &output, weld_epsilon)
+        },
+        Command::Batch { input_dir, output_dir, include, exclude, max_volume, weld_epsilon, jobs } => {
+            let options = BatchOptions {
+                include,
+                exclude,
+                mode: max_volume.map_or(FillMode::All, FillMode::BelowVolume),
+                weld_epsilon,
+            };
+            batch(&input_dir, &output_dir, &options, jobs)
+        },
+        Command::Info { input, weld_epsilon, json } => info(&input, weld_epsilon, json),
+        Command::Validate { input, weld_epsilon, json } => validate(&input, weld_epsilon, json),
+        Command::View { input } => view(&input),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// model.stl -> model_solid.stl, model.obj -> model_solid.obj
+fn solid_path(input: &Path) -> PathBuf {
+    suffixed_path(input, "solid")
+}
+
+fn suffixed_path(input: &Path, suffix: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = input.extension().unwrap_or("stl".as_ref()).to_string_lossy();
+    input.with_file_name(format!("{}_{}.{}", stem, suffix, extension))
+}
+
+fn fill(input: &Path, output: &Path, mode: FillMode, weld_epsilon: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    let (solid, summaries) = fill_scene(&scene, mode, weld_epsilon)?;
+    for (mesh, summary) in solid.meshes.iter().zip(&summaries) {
+        println!("{}: {}", mesh.name, summary);
+    }
+    save(&solid, output)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+fn voxel_fill(input: &Path, output: &Path, voxel_size: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    let (solid, reports) = voxel_fill_scene(&scene, voxel_size)?;
+    for (mesh, report) in solid.meshes.iter().zip(&reports) {
+        print!("{}: {}", mesh.name, report);
+    }
+    save(&solid, output)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+fn repair(input: &Path, output: &Path, limit: HoleLimit, weld_epsilon: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    let mut repaired = Scene { header: scene.header.clone(), meshes: Vec::new() };
+    for mesh in &scene.meshes {
+        let (cleaned, cleanup) = clean(&weld(mesh, weld_epsilon));
+        // Holes first, so orientation is decided on closed shells
+        let (filled, holes) = fill_holes(&cleaned, limit);
+        let (oriented, orientation) = orient(&filled);
+        // Merging needs closed, consistently wound parts to tell inside from out
+        let (merged, union) = self_union(&oriented)?;
+        print!("{}: {}", mesh.name, cleanup);
+        print!("{}: {}", mesh.name, holes);
+        print!("{}: {}", mesh.name, orientation);
+        print!("{}: {}", mesh.name, union);
+        let disagreeing = disagreeing_normals(mesh);
+        if disagreeing > 0 {
+            println!("{}: recomputed {} stored normals that disagreed with the winding", mesh.name, disagreeing);
+        }
+        repaired.meshes.push(merged);
+    }
+    save(&repaired, output)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+fn combine(
+    operation: BooleanOp,
+    first: &Path,
+    second: Option<&Path>,
+    output: &Path,
+    weld_epsilon: f32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(first)?;
+    let (a, b) = match second {
+        Some(second) => (merged(&scene), merged(&load(second)?)),
+        None => match &scene.meshes[..] {
+            [a, b] => (a.clone(), b.clone()),
+            meshes => return Err(format!("{} has {} meshes, give the second mesh as its own file", first.display(), meshes.len()).into()),
+        },
+    };
+
+    // Booleans need each mesh outward wound to know which side is solid
+    let prepare = |mesh: &TriMesh| orient(&clean(&weld(mesh, weld_epsilon)).0).0;
+    let (result, report) = boolean(&prepare(&a), &prepare(&b), operation)?;
+    print!("{}: {}", result.name, report);
+    save(&Scene { header: scene.header.clone(), meshes: vec![result] }, output)?;
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+// Every mesh of a file as one, named after the first
+fn merged(scene: &Scene) -> TriMesh {
+    let mut mesh = TriMesh::new(scene.meshes.first().map_or("", |mesh| &mesh.name));
+    for other in &scene.meshes {
+        mesh.append(other);
+    }
+    mesh
+}
+
+fn batch(
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &BatchOptions,
+    jobs: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+    }
+
+    let report = run_batch(input_dir, output_dir, options)?;
+    println!("{}", report);
+    match report.failures() {
+        0 => Ok(()),
+        failures => Err(format!("{} of {} files failed", failures, report.results.len()).into()),
+    }
+}
+
+fn info(input: &Path, weld_epsilon: f32, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    let report = analyze(&input.display().to_string(), &scene, weld_epsilon)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
+
+fn validate(input: &Path, weld_epsilon: f32, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    let report = validate_scene(&input.display().to_string(), &scene, weld_epsilon);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report);
+    }
+    match report.num_problems() {
+        0 => Ok(()),
+        problems => Err(format!("{} problems found", problems).into()),
+    }
+}
+
+fn view(input: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let scene = load(input)?;
+    pollster::block_on(solidify::window::run(scene, solid_path(input).with_extension("stl")));
+    Ok(())
 }
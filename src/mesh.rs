@@ -0,0 +1,242 @@
+use cgmath::{InnerSpace, Vector3};
+
+// Plain triangle mesh that lives on the CPU so it can be processed
+// without a GPU. Model::upload turns it into something drawable.
+#[derive(Clone, Debug, Default)]
+pub struct TriMesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+    // Per vertex, same length as positions when present
+    pub normals: Option<Vec<[f32; 3]>>,
+    // Per triangle as stored in the file, same length as triangles when
+    // present. Only kept to check against the winding, which is what
+    // normals are recomputed from.
+    pub face_normals: Option<Vec<[f32; 3]>>,
+    // Per vertex RGB in 0..1, same length as positions when present
+    pub colors: Option<Vec<[f32; 3]>>,
+    // Per triangle RGB in 0..1, same length as triangles when present.
+    // STL colors facets rather than vertices, and welding would blur them.
+    // Facets a file leaves uncolored stay None so saving doesn't color them.
+    pub face_colors: Option<Vec<Option<[f32; 3]>>>,
+}
+
+// Triangles with less area than this are treated as slivers with no area at all
+pub const DEGENERATE_AREA: f64 = 1e-12;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+// A set of meshes loaded from (or about to be written to) a single file
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    // Free-form file header, the raw 80 bytes for binary STL
+    pub header: Vec<u8>,
+    pub meshes: Vec<TriMesh>,
+}
+
+impl TriMesh {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn triangle(&self, index: usize) -> [[f32; 3]; 3] {
+        let [a, b, c] = self.triangles[index];
+        [
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize]
+        ]
+    }
+
+    // Normal from the winding order, zero for degenerate triangles
+    pub fn facet_normal(&self, index: usize) -> [f32; 3] {
+        let [p1, p2, p3] = self.triangle(index);
+        facet_normal(p1, p2, p3)
+    }
+
+    // Adds another mesh's triangles to this one. Normals and colors are
+    // kept only if both meshes have them.
+    pub fn append(&mut self, other: &TriMesh) {
+        let offset = self.positions.len() as u32;
+        let empty = self.positions.is_empty();
+        self.normals = append_attribute(self.normals.take(), &other.normals, empty);
+        self.face_normals = append_attribute(self.face_normals.take(), &other.face_normals, self.triangles.is_empty());
+        self.colors = append_attribute(self.colors.take(), &other.colors, empty);
+        self.face_colors = append_attribute(self.face_colors.take(), &other.face_colors, self.triangles.is_empty());
+        self.positions.extend_from_slice(&other.positions);
+        self.triangles.extend(other.triangles.iter().map(|tri| tri.map(|index| index + offset)));
+    }
+
+    // Bounds of the vertices actually used by triangles, None for an empty mesh
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.triangles.iter()
+            .flatten()
+            .map(|&index| self.positions[index as usize])
+            .fold(None, |bounds: Option<Bounds>, position| Some(match bounds {
+                Some(bounds) => bounds.including(position),
+                None => Bounds { min: position, max: position },
+            }))
+    }
+
+    // Sum of signed tetrahedra against the origin. Positive when the
+    // triangles wind counter-clockwise seen from outside, negative when
+    // the mesh is inside out, only meaningful for closed meshes.
+    pub fn signed_volume(&self) -> f64 {
+        (0..self.num_triangles())
+            .map(|index| {
+                let [a, b, c] = self.triangle(index).map(|p| Vector3::from(p).cast::<f64>().unwrap());
+                a.dot(b.cross(c)) / 6.0
+            })
+            .sum()
+    }
+
+    pub fn is_degenerate(&self, index: usize) -> bool {
+        let [a, b, c] = self.triangles[index];
+        a == b || b == c || c == a || triangle_area(self.triangle(index)) <= DEGENERATE_AREA
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        (0..self.num_triangles())
+            .map(|index| triangle_area(self.triangle(index)))
+            .sum()
+    }
+}
+
+impl Bounds {
+    pub fn including(self, position: [f32; 3]) -> Self {
+        Self {
+            min: [0, 1, 2].map(|i| self.min[i].min(position[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(position[i])),
+        }
+    }
+
+    pub fn size(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+
+    pub fn contains(&self, other: &Bounds) -> bool {
+        (0..3).all(|i| self.min[i] <= other.min[i] && other.max[i] <= self.max[i])
+    }
+}
+
+impl Scene {
+    pub fn num_triangles(&self) -> usize {
+        self.meshes.iter().map(TriMesh::num_triangles).sum()
+    }
+}
+
+fn append_attribute<T: Clone>(
+    attribute: Option<Vec<T>>,
+    other: &Option<Vec<T>>,
+    empty: bool,
+) -> Option<Vec<T>> {
+    match (attribute, other) {
+        (Some(mut values), Some(other_values)) => {
+            values.extend_from_slice(other_values);
+            Some(values)
+        },
+        (None, Some(other_values)) if empty => Some(other_values.clone()),
+        _ => None,
+    }
+}
+
+pub fn triangle_area([p1, p2, p3]: [[f32; 3]; 3]) -> f64 {
+    let a = Vector3::from(p1).cast::<f64>().unwrap();
+    let b = Vector3::from(p2).cast::<f64>().unwrap();
+    let c = Vector3::from(p3).cast::<f64>().unwrap();
+    (b - a).cross(c - a).magnitude() / 2.0
+}
+
+pub fn facet_normal(p1: [f32; 3], p2: [f32; 3], p3: [f32; 3]) -> [f32; 3] {
+    let a = Vector3::from(p1);
+    let normal = (Vector3::from(p2) - a).cross(Vector3::from(p3) - a);
+    if normal.magnitude2() == 0.0 {
+        return [0.0, 0.0, 0.0];
+    }
+    // Adding zero turns -0.0 into 0.0, which is what exporters write
+    (normal.normalize() + Vector3::new(0.0, 0.0, 0.0)).into()
+}
+
+// Newell's method, robust for non-planar and concave polygons. Points
+// the way the polygon winds counter-clockwise, with twice its area as length.
+pub fn polygon_normal(points: &[[f32; 3]]) -> Vector3<f64> {
+    let mut normal = Vector3::new(0.0f64, 0.0, 0.0);
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        normal.x += (a[1] - b[1]) as f64 * (a[2] + b[2]) as f64;
+        normal.y += (a[2] - b[2]) as f64 * (a[0] + b[0]) as f64;
+        normal.z += (a[0] - b[0]) as f64 * (a[1] + b[1]) as f64;
+    }
+    normal
+}
+
+// Ear clipping in the polygon's best fit plane, so concave faces from OBJ
+// and PLY files don't get triangles outside their outline. Falls back to a
+// fan for whatever is left if the polygon is too twisted to find an ear.
+pub fn triangulate_polygon(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let normal = polygon_normal(points);
+
+    // Project onto the plane the normal is most aligned with, flipping an
+    // axis when needed so the polygon always winds counter-clockwise in 2D
+    let (u, v, flip) = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+        (1, 2, normal.x < 0.0)
+    } else if normal.y.abs() >= normal.z.abs() {
+        (2, 0, normal.y < 0.0)
+    } else {
+        (0, 1, normal.z < 0.0)
+    };
+    let flat: Vec<[f64; 2]> = points.iter()
+        .map(|p| [p[u] as f64, if flip { -(p[v] as f64) } else { p[v] as f64 }])
+        .collect();
+    let cross = |a: [f64; 2], b: [f64; 2], c: [f64; 2]| (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            if cross(flat[a], flat[b], flat[c]) <= 0.0 {
+                return false;
+            }
+            // No other vertex may sit inside the ear
+            remaining.iter()
+                .filter(|&&other| other != a && other != b && other != c)
+                .all(|&other| {
+                    let p = flat[other];
+                    cross(flat[a], flat[b], p) < 0.0 || cross(flat[b], flat[c], p) < 0.0 || cross(flat[c], flat[a], p) < 0.0
+                })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+                remaining.remove(i);
+            },
+            None => break,
+        }
+    }
+
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
@@ -1,7 +1,9 @@
-use std::{fs::{self, File}, io::Read, ops::Range};
+use std::ops::Range;
 
 use wgpu::util::DeviceExt;
 
+use crate::mesh::{facet_normal, Scene, TriMesh};
+
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
 }
@@ -24,6 +26,76 @@ pub struct Mesh {
     // pub material: usize,
 }
 
+impl Model {
+    pub fn upload(scene: &Scene, device: &wgpu::Device) -> Self {
+        Self {
+            meshes: scene.meshes.iter()
+                .map(|mesh| Mesh::upload(mesh, device))
+                .collect()
+        }
+    }
+}
+
+impl Mesh {
+    pub fn upload(mesh: &TriMesh, device: &wgpu::Device) -> Self {
+        // Meshes without vertex colors draw white
+        let color = |index: u32| mesh.colors.as_ref().map_or([1.0, 1.0, 1.0], |colors| colors[index as usize]);
+        let tex_coords = [0.0, 0.0];
+        let mut verts: Vec<ModelVertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        match &mesh.normals {
+            Some(normals) if mesh.face_colors.is_none() => {
+                verts.extend(mesh.positions.iter().zip(normals).enumerate().map(|(index, (&position, &normal))| ModelVertex {
+                    position,
+                    color: color(index as u32),
+                    normal,
+                    tex_coords
+                }));
+                indices.extend(mesh.triangles.iter().flatten());
+            },
+            // Without vertex normals every triangle gets its own vertices so it
+            // shades flat, which also keeps facet colors from bleeding
+            _ => {
+                for index in 0..mesh.num_triangles() {
+                    let [pos1, pos2, pos3] = mesh.triangle(index);
+                    let normal = facet_normal(pos1, pos2, pos3);
+                    let current_index = verts.len() as u32;
+                    let corners = mesh.triangles[index];
+                    let face_color = mesh.face_colors.as_ref().and_then(|colors| colors[index]);
+                    verts.extend([pos1, pos2, pos3].iter().zip(corners).map(|(&position, corner)| ModelVertex {
+                        position,
+                        color: face_color.unwrap_or_else(|| color(corner)),
+                        normal,
+                        tex_coords
+                    }));
+                    indices.extend_from_slice(&[current_index, current_index + 1, current_index + 2]);
+                }
+            }
+        }
+
+        // build all the mesh shenanagains
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", mesh.name)),
+            contents: bytemuck::cast_slice(&verts),
+            usage: wgpu::BufferUsages::VERTEX
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", mesh.name)),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX
+        });
+
+        Self {
+            name: mesh.name.clone(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+        }
+    }
+}
+
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b: 'a, {
     fn draw_mesh(&mut self, mesh: &'b Mesh) {
         self.draw_mesh_instanced(mesh, 0..1);
@@ -34,146 +106,17 @@ impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b: 'a, {
         mesh: &'b Mesh, 
         instances: Range<u32>
     ) {
+        self.push_debug_group(&mesh.name);
         self.set_vertex_buffer(
             0, 
             mesh.vertex_buffer.slice(..)
         );
         self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
+        self.pop_debug_group();
     }
 }
 
-fn f32_from_vec_offset(vec: &Vec<u8>, offset: usize) -> f32 {
-    f32::from_le_bytes([
-        vec[offset],
-        vec[offset + 1],
-        vec[offset + 2],
-        vec[offset + 3]
-    ])
-}
-
-const STL_HEADER_SIZE: u64 = 80;
-// TODO: Make this faster
-pub async fn load_stl(
-    file_path: &str,
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> Model {
-    let mut file = File::open(file_path).unwrap();
-    // Read the file header -> 80 bytes
-    // Read the number of triangles -> u32
-
-    let mut header_buffer = Vec::with_capacity(STL_HEADER_SIZE as usize);
-    _ = file.by_ref()
-        .take(STL_HEADER_SIZE)
-        .read_to_end(&mut header_buffer).unwrap();
-
-    // Get the number of iterations
-    let mut size_buffer: Vec<u8> = Vec::with_capacity(std::mem::size_of::<u32>());
-    _ = file.by_ref()
-        .take(std::mem::size_of::<u32>() as u64)
-        .read_to_end(&mut size_buffer).unwrap();
-
-    let num_tris = u32::from_le_bytes([size_buffer[0], size_buffer[1], size_buffer[2], size_buffer[3]]);
-
-    let mut verts: Vec<ModelVertex> = Vec::new();
-    let mut indices: Vec<u32> = Vec::new();
-    let mut current_index: u32 = 0;
-    let triangle_size: u64 = (12 * 4)+2;
-    let f32_size = std::mem::size_of::<f32>();
-    let color = [1.0, 1.0, 1.0];
-    let tex_coords = [0.0, 0.0];
-    for _ in 0..num_tris {
-        let mut tri_buffer: Vec<u8> = Vec::with_capacity(triangle_size as usize);
-        _ = file.by_ref()
-            .take(triangle_size)
-            .read_to_end(&mut tri_buffer);
-        
-
-        // TODO: Roll this into a new function
-        let normal = [
-            f32_from_vec_offset(&tri_buffer, 0),
-            f32_from_vec_offset(&tri_buffer, f32_size),
-            f32_from_vec_offset(&tri_buffer, 2 * f32_size)
-        ];
-
-        let pos1 = [
-            f32_from_vec_offset(&tri_buffer, 3 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 4 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 5 * f32_size)
-        ];
-
-        let pos2 = [
-            f32_from_vec_offset(&tri_buffer, 6 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 7 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 8 * f32_size)
-        ];
-
-        let pos3 = [
-            f32_from_vec_offset(&tri_buffer, 9 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 10 * f32_size),
-            f32_from_vec_offset(&tri_buffer, 11 * f32_size)
-        ];
-
-        let mut tri_verts = vec![
-            ModelVertex {
-                position: pos1,
-                color,
-                normal,
-                tex_coords
-            },
-            ModelVertex {
-                position: pos2,
-                color,
-                normal,
-                tex_coords
-            },
-            ModelVertex {
-                position: pos3,
-                color,
-                normal,
-                tex_coords
-            }
-        ];
-        
-        verts.append(&mut tri_verts);
-
-        let mut tri_indices = vec![current_index, current_index+1, current_index+2];
-        indices.append(&mut tri_indices);
-        current_index += 3;
-    }
-
-    // build all the mesh shenanagains
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Vertex Buffer", file_path)), // TODO: Use just the file name instead of the full path
-        contents: bytemuck::cast_slice(&verts),
-        usage: wgpu::BufferUsages::VERTEX
-    });
-
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Index Buffer", file_path)),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX
-    });
-
-    Model {
-        meshes: vec![
-            Mesh {
-                name: format!("{:?}", file_path),
-                vertex_buffer,
-                index_buffer,
-                num_elements: indices.len() as u32,
-            }
-        ]
-    }
-}
-
-pub async fn save_stl(
-    model: Model,
-    file_path: String
-) {
-    todo!()
-}
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
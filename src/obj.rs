@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::mesh::{triangulate_polygon, Scene, TriMesh};
+
+#[derive(Debug)]
+pub enum ObjError {
+    NotFound(String),
+    InvalidLine { line: usize },
+    IndexOutOfRange { line: usize, index: i64 },
+    InvalidCoordinate { line: usize },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::NotFound(path) => write!(f, "{} does not exist", path),
+            ObjError::InvalidLine { line } => write!(f, "malformed OBJ on line {}", line),
+            ObjError::IndexOutOfRange { line, index } => write!(
+                f, "face on line {} references missing vertex {}", line, index),
+            ObjError::InvalidCoordinate { line } => write!(
+                f, "vertex on line {} has a NaN or infinite coordinate", line),
+            ObjError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ObjError {
+    fn from(e: std::io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+// A group being built. Vertices are unique per position/normal pair used
+// by its faces, so a group only carries the vertices it references.
+struct Group {
+    mesh: TriMesh,
+    normals: Vec<[f32; 3]>,
+    all_normals: bool,
+    lookup: HashMap<(usize, Option<usize>), u32>,
+}
+
+impl Group {
+    fn new(name: &str) -> Self {
+        Self {
+            mesh: TriMesh::new(name),
+            normals: Vec::new(),
+            all_normals: true,
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(&mut self, position: usize, normal: Option<usize>, positions: &[[f32; 3]], normals: &[[f32; 3]]) -> u32 {
+        *self.lookup.entry((position, normal)).or_insert_with(|| {
+            self.mesh.positions.push(positions[position]);
+            match normal {
+                Some(normal) => self.normals.push(normals[normal]),
+                None => {
+                    self.all_normals = false;
+                    self.normals.push([0.0; 3]);
+                }
+            }
+            self.mesh.positions.len() as u32 - 1
+        })
+    }
+
+    fn finish(mut self) -> TriMesh {
+        if self.all_normals {
+            self.mesh.normals = Some(self.normals);
+        }
+        self.mesh
+    }
+}
+
+// OBJ indices are 1-based, negative ones count back from the latest vertex
+fn resolve_index(token: &str, count: usize, line: usize) -> Result<usize, ObjError> {
+    let index: i64 = token.parse().map_err(|_| ObjError::InvalidLine { line })?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::IndexOutOfRange { line, index });
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_vec3<'a>(tokens: &mut impl Iterator<Item = &'a str>, line: usize) -> Result<[f32; 3], ObjError> {
+    let mut value = [0.0; 3];
+    for component in value.iter_mut() {
+        *component = tokens.next()
+            .and_then(|token| token.parse().ok())
+            .ok_or(ObjError::InvalidLine { line })?;
+    }
+    if value.iter().any(|v: &f32| !v.is_finite()) {
+        return Err(ObjError::InvalidCoordinate { line });
+    }
+    Ok(value)
+}
+
+// OBJ has no magic number, so look for text whose first statement is
+// something only an OBJ file would start with
+pub fn is_obj(sample: &[u8]) -> bool {
+    if sample.iter().any(|b| b.is_ascii_control() && !b.is_ascii_whitespace()) {
+        return false;
+    }
+    String::from_utf8_lossy(sample)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .and_then(|line| line.split_whitespace().next())
+        .is_some_and(|keyword| matches!(keyword, "v" | "vn" | "vt" | "f" | "o" | "g" | "s" | "mtllib" | "usemtl"))
+}
+
+// Reads v/vn/f, every g or o starts a new mesh. Texture coordinates,
+// materials and smoothing groups are skipped.
+pub fn load_obj(file_path: impl AsRef<Path>) -> Result<Scene, ObjError> {
+    let file_path = file_path.as_ref();
+    let file = File::open(file_path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ObjError::NotFound(file_path.display().to_string()),
+        _ => ObjError::Io(e),
+    })?;
+    let name = file_path
+        .file_stem()
+        .map_or_else(|| file_path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+
+    let mut header = Vec::new();
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut meshes = Vec::new();
+    let mut group = Group::new(&name);
+    let mut polygon: Vec<(usize, Option<usize>)> = Vec::new();
+
+    for (line_index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line_number = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            // Leading comments become the header so they survive a round trip
+            Some(comment) if comment.starts_with('#') && positions.is_empty() => {
+                let text = line.trim_start()[1..].trim();
+                if !header.is_empty() {
+                    header.push(b'\n');
+                }
+                header.extend_from_slice(text.as_bytes());
+            },
+            Some("v") => positions.push(parse_vec3(&mut tokens, line_number)?),
+            Some("vn") => normals.push(parse_vec3(&mut tokens, line_number)?),
+            Some("g") | Some("o") => {
+                let group_name = tokens.collect::<Vec<_>>().join(" ");
+                let group_name = if group_name.is_empty() { name.clone() } else { group_name };
+                let finished = std::mem::replace(&mut group, Group::new(&group_name));
+                if finished.mesh.num_triangles() > 0 {
+                    meshes.push(finished.finish());
+                }
+            },
+            Some("f") => {
+                polygon.clear();
+                for corner in tokens {
+                    // v, v/vt, v//vn or v/vt/vn
+                    let mut parts = corner.split('/');
+                    let position = resolve_index(parts.next().unwrap_or(""), positions.len(), line_number)?;
+                    let normal = match parts.nth(1) {
+                        Some(token) if !token.is_empty() => Some(resolve_index(token, normals.len(), line_number)?),
+                        _ => None,
+                    };
+                    polygon.push((position, normal));
+                }
+                if polygon.len() < 3 {
+                    return Err(ObjError::InvalidLine { line: line_number });
+                }
+
+                let points: Vec<[f32; 3]> = polygon.iter().map(|&(position, _)| positions[position]).collect();
+                for tri in triangulate_polygon(&points) {
+                    let tri = tri.map(|corner| {
+                        let (position, normal) = polygon[corner];
+                        group.vertex(position, normal, &positions, &normals)
+                    });
+                    group.mesh.triangles.push(tri);
+                }
+            },
+            _ => {}
+        }
+    }
+    if group.mesh.num_triangles() > 0 || meshes.is_empty() {
+        meshes.push(group.finish());
+    }
+
+    Ok(Scene { header, meshes })
+}
+
+// One object per mesh, vertex indices are global across the file
+pub fn save_obj(scene: &Scene, file_path: impl AsRef<Path>) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = BufWriter::new(File::create(file_path)?);
+    let header = String::from_utf8_lossy(&scene.header);
+    for line in header.trim_end_matches('\0').lines() {
+        writeln!(file, "# {}", line.trim_end_matches('\0'))?;
+    }
+
+    let mut offset = 1;
+    let mut normal_offset = 1;
+    for mesh in &scene.meshes {
+        writeln!(file, "o {}", mesh.name)?;
+        for [x, y, z] in &mesh.positions {
+            writeln!(file, "v {} {} {}", x, y, z)?;
+        }
+        if let Some(normals) = &mesh.normals {
+            for [x, y, z] in normals {
+                writeln!(file, "vn {} {} {}", x, y, z)?;
+            }
+        }
+        for tri in &mesh.triangles {
+            let [a, b, c] = tri.map(|index| index as usize + offset);
+            if mesh.normals.is_some() {
+                let [na, nb, nc] = tri.map(|index| index as usize + normal_offset);
+                writeln!(file, "f {}//{} {}//{} {}//{}", a, na, b, nb, c, nc)?;
+            } else {
+                writeln!(file, "f {} {} {}", a, b, c)?;
+            }
+        }
+        offset += mesh.positions.len();
+        if mesh.normals.is_some() {
+            normal_offset += mesh.positions.len();
+        }
+    }
+
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+
+    const TWO_GROUPS: &str = "# made by hand
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+g square
+f 1//1 2//1 3//1 4//1
+o triangle
+v 0 0 1
+f -5 -4 -1
+";
+
+    #[test]
+    fn loads_groups_and_polygons() {
+        let path = temp_path("groups.obj");
+        std::fs::write(&path, TWO_GROUPS).unwrap();
+        let scene = load_obj(&path).unwrap();
+        assert_eq!(scene.header, b"made by hand");
+        assert_eq!(scene.meshes.len(), 2);
+
+        let square = &scene.meshes[0];
+        assert_eq!(square.name, "square");
+        assert_eq!(square.num_triangles(), 2);
+        assert_eq!(square.positions.len(), 4);
+        assert_eq!(square.normals, Some(vec![[0.0, 0.0, 1.0]; 4]));
+        assert!((square.surface_area() - 1.0).abs() < 1e-9);
+
+        let triangle = &scene.meshes[1];
+        assert_eq!(triangle.name, "triangle");
+        assert_eq!(triangle.triangle(0), [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(triangle.normals, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saves_and_loads_back() {
+        let path = temp_path("round_trip.obj");
+        let mut second = cube("second", [2.0; 3], [3.0; 3]);
+        second.normals = Some(vec![[0.0, 0.0, 1.0]; second.positions.len()]);
+        let scene = Scene {
+            header: b"first line\nsecond line".to_vec(),
+            meshes: vec![cube("first", [0.0; 3], [1.0; 3]), second],
+        };
+        save_obj(&scene, &path).unwrap();
+
+        let loaded = load_obj(&path).unwrap();
+        assert_eq!(loaded.header, scene.header);
+        assert_eq!(loaded.meshes.len(), 2);
+        for (loaded, saved) in loaded.meshes.iter().zip(&scene.meshes) {
+            assert_eq!(loaded.name, saved.name);
+            // Vertices come back in the order faces first use them
+            let corners = |mesh: &TriMesh| (0..mesh.num_triangles()).map(|face| mesh.triangle(face)).collect::<Vec<_>>();
+            assert_eq!(corners(loaded), corners(saved));
+            assert_eq!(loaded.positions.len(), saved.positions.len());
+            assert_eq!(loaded.normals.is_some(), saved.normals.is_some());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn load_error(name: &str, contents: &str) -> ObjError {
+        let path = temp_path(name);
+        std::fs::write(&path, contents).unwrap();
+        let error = load_obj(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        error
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let error = load_error("out_of_range.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n");
+        assert!(matches!(error, ObjError::IndexOutOfRange { line: 4, index: 4 }), "{:?}", error);
+        let error = load_error("short_vertex.obj", "v 0 0 0\nv 1 0\n");
+        assert!(matches!(error, ObjError::InvalidLine { line: 2 }), "{:?}", error);
+        let error = load_error("nan_vertex.obj", "v 0 0 0\nv 1 0 nan\n");
+        assert!(matches!(error, ObjError::InvalidCoordinate { line: 2 }), "{:?}", error);
+        let error = load_error("short_face.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n");
+        assert!(matches!(error, ObjError::InvalidLine { line: 3 }), "{:?}", error);
+    }
+}
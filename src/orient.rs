@@ -0,0 +1,213 @@
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+
+use crate::cavity::{find_cavities, ShellKind};
+use crate::mesh::TriMesh;
+use crate::shell::extract_shells;
+use crate::topology::Topology;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OrientReport {
+    // Faces flipped to agree with their neighbours
+    pub flipped_faces: usize,
+    // Shells turned inside out so outer surfaces face out and cavities in
+    pub reversed_shells: usize,
+    // Edges still wound the same way from both sides, only possible on
+    // non-orientable surfaces like a Möbius strip
+    pub conflicting_edges: usize,
+}
+
+// Makes every face agree with its neighbours across manifold edges, then
+// turns each shell so outer surfaces have positive volume and cavities
+// negative, which is what slicers expect. Expects a welded mesh. Normals
+// are dropped so everything downstream recomputes them from the winding.
+pub fn orient(mesh: &TriMesh) -> (TriMesh, OrientReport) {
+    let topology = Topology::new(mesh);
+    let mut report = OrientReport::default();
+
+    let flip = propagate_orientation(&topology, &mut report);
+    let mut consistent = mesh.clone();
+    consistent.normals = None;
+    consistent.face_normals = None;
+    for (tri, &flip) in consistent.triangles.iter_mut().zip(&flip) {
+        if flip {
+            tri.swap(1, 2);
+            report.flipped_faces += 1;
+        }
+    }
+
+    let shells = extract_shells(&consistent);
+    let cavities = find_cavities(&shells);
+    let mut oriented = TriMesh::new(&mesh.name);
+    for (shell, classification) in shells.iter().zip(&cavities.shells) {
+        let want_positive = classification.kind == ShellKind::Outer;
+        let mut shell_mesh = shell.mesh.clone();
+        if shell.stats.signed_volume != 0.0 && (shell.stats.signed_volume > 0.0) != want_positive {
+            for tri in &mut shell_mesh.triangles {
+                tri.swap(1, 2);
+            }
+            report.reversed_shells += 1;
+        }
+        oriented.append(&shell_mesh);
+    }
+
+    (oriented, report)
+}
+
+// Breadth first over manifold edges, deciding for every face whether it
+// needs flipping to match the face it was reached from. Each patch keeps
+// whichever orientation most of its faces already had.
+fn propagate_orientation(topology: &Topology, report: &mut OrientReport) -> Vec<bool> {
+    let num_faces = topology.triangles.len();
+    let mut flip: Vec<Option<bool>> = vec![None; num_faces];
+
+    for start in 0..num_faces {
+        if flip[start].is_some() {
+            continue;
+        }
+
+        flip[start] = Some(false);
+        let mut patch = vec![start as u32];
+        let mut next = 0;
+        while next < patch.len() {
+            let face = patch[next];
+            next += 1;
+            for &edge in &topology.face_edges[face as usize] {
+                let edge = &topology.edges[edge as usize];
+                if edge.faces.len() != 2 {
+                    continue;
+                }
+                let neighbour = if edge.faces[0] == face { edge.faces[1] } else { edge.faces[0] };
+                // Neighbours that agree walk their shared edge in opposite directions
+                let agree = topology.directed_edge(face, edge.vertices) != topology.directed_edge(neighbour, edge.vertices);
+                let wanted = if agree { flip[face as usize].unwrap() } else { !flip[face as usize].unwrap() };
+                match flip[neighbour as usize] {
+                    None => {
+                        flip[neighbour as usize] = Some(wanted);
+                        patch.push(neighbour);
+                    },
+                    // Each conflicting edge is seen once from either side
+                    Some(existing) if existing != wanted && face < neighbour => report.conflicting_edges += 1,
+                    Some(_) => {},
+                }
+            }
+        }
+
+        let flipped = patch.iter().filter(|&&face| flip[face as usize] == Some(true)).count();
+        if flipped * 2 > patch.len() {
+            for &face in &patch {
+                flip[face as usize] = flip[face as usize].map(|flip| !flip);
+            }
+        }
+    }
+
+    flip.into_iter().map(|flip| flip.unwrap_or(false)).collect()
+}
+
+// Facets whose stored normal points against the normal their winding
+// gives, for reporting how far a file's normals could be trusted
+pub fn disagreeing_normals(mesh: &TriMesh) -> usize {
+    let stored = |face: usize| -> Option<Vector3<f32>> {
+        match (&mesh.face_normals, &mesh.normals) {
+            (Some(face_normals), _) => Some(Vector3::from(face_normals[face])),
+            (None, Some(normals)) => Some(mesh.triangles[face].iter().map(|&vertex| Vector3::from(normals[vertex as usize])).sum()),
+            (None, None) => None,
+        }
+    };
+    (0..mesh.num_triangles())
+        .filter(|&face| stored(face).is_some_and(|stored| stored.dot(Vector3::from(mesh.facet_normal(face))) < 0.0))
+        .count()
+}
+
+impl fmt::Display for OrientReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flipped {} faces to match their neighbours, turned {} shells the right way out",
+            self.flipped_faces, self.reversed_shells
+        )?;
+        if self.conflicting_edges > 0 {
+            write!(f, ", {} edges can't be made consistent", self.conflicting_edges)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, inverted};
+
+    fn consistent(mesh: &TriMesh) -> bool {
+        let topology = Topology::new(mesh);
+        topology.edges.iter().all(|edge| {
+            edge.faces.len() == 2
+                && topology.directed_edge(edge.faces[0], edge.vertices) != topology.directed_edge(edge.faces[1], edge.vertices)
+        })
+    }
+
+    fn flip_faces(mesh: &mut TriMesh, faces: impl IntoIterator<Item = usize>) {
+        for face in faces {
+            mesh.triangles[face].swap(1, 2);
+        }
+    }
+
+    #[test]
+    fn flips_faces_to_match_their_neighbours() {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        flip_faces(&mut mesh, [3]);
+        let (oriented, report) = orient(&mesh);
+        assert_eq!((report.flipped_faces, report.reversed_shells, report.conflicting_edges), (1, 0, 0));
+        assert!(consistent(&oriented));
+        assert!((oriented.signed_volume() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn keeps_the_orientation_most_faces_have() {
+        // Most faces point in, so the other five get flipped to match and
+        // the shell is then turned the right way out
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        flip_faces(&mut mesh, 0..7);
+        let (oriented, report) = orient(&mesh);
+        assert_eq!((report.flipped_faces, report.reversed_shells), (5, 1));
+        assert!(consistent(&oriented));
+        assert!((oriented.signed_volume() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn turns_inside_out_shells_around() {
+        let (oriented, report) = orient(&inverted(cube("cube", [0.0; 3], [10.0; 3])));
+        assert_eq!((report.flipped_faces, report.reversed_shells), (0, 1));
+        assert!((oriented.signed_volume() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn winds_cavities_inwards() {
+        for (inner, reversed) in [(inverted(cube("hollow", [3.0; 3], [7.0; 3])), 0), (cube("hollow", [3.0; 3], [7.0; 3]), 1)] {
+            let mut mesh = cube("hollow", [0.0; 3], [10.0; 3]);
+            mesh.append(&inner);
+            let (oriented, report) = orient(&mesh);
+            assert_eq!(report.reversed_shells, reversed);
+            assert!((oriented.signed_volume() - 936.0).abs() < 1e-6);
+            let volumes: Vec<f64> = extract_shells(&oriented).iter().map(|shell| shell.stats.signed_volume).collect();
+            assert_eq!(volumes.len(), 2);
+            assert!(volumes[0] > 0.0 && volumes[1] < 0.0);
+        }
+    }
+
+    #[test]
+    fn counts_stored_normals_against_the_winding() {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        assert_eq!(disagreeing_normals(&mesh), 0);
+        let mut face_normals: Vec<[f32; 3]> = (0..mesh.num_triangles()).map(|face| mesh.facet_normal(face)).collect();
+        mesh.face_normals = Some(face_normals.clone());
+        assert_eq!(disagreeing_normals(&mesh), 0);
+        face_normals[4] = face_normals[4].map(|v| -v);
+        mesh.face_normals = Some(face_normals);
+        assert_eq!(disagreeing_normals(&mesh), 1);
+        // Orienting drops the stored normals
+        assert!(orient(&mesh).0.face_normals.is_none());
+    }
+}
@@ -0,0 +1,541 @@
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::Path;
+
+use crate::mesh::{triangulate_polygon, Scene, TriMesh};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    NotFound(String),
+    NotPly,
+    InvalidHeader { line: usize },
+    UnexpectedEnd { element: String },
+    InvalidValue { element: String, index: usize },
+    IndexOutOfRange { face: usize, index: i64 },
+    InvalidCoordinate { vertex: usize },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::NotFound(path) => write!(f, "{} does not exist", path),
+            PlyError::NotPly => write!(f, "file does not start with a PLY header"),
+            PlyError::InvalidHeader { line } => write!(f, "malformed PLY header on line {}", line),
+            PlyError::UnexpectedEnd { element } => write!(f, "file ends in the middle of the {} data", element),
+            PlyError::InvalidValue { element, index } => write!(f, "{} {} has a malformed value", element, index),
+            PlyError::IndexOutOfRange { face, index } => write!(
+                f, "face {} references missing vertex {}", face, index),
+            PlyError::InvalidCoordinate { vertex } => write!(
+                f, "vertex {} has a NaN or infinite coordinate", vertex),
+            PlyError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PlyError {
+    fn from(e: std::io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // Integer colors use the type's full range, float colors are already 0..1
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Int8 => i8::MAX as f64,
+            ScalarType::UInt8 => u8::MAX as f64,
+            ScalarType::Int16 => i16::MAX as f64,
+            ScalarType::UInt16 => u16::MAX as f64,
+            ScalarType::Int32 => i32::MAX as f64,
+            ScalarType::UInt32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar { name: String, kind: ScalarType },
+    List { name: String, count: ScalarType, item: ScalarType },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: PlyFormat,
+    comments: Vec<String>,
+    elements: Vec<Element>,
+    // Byte offset of the first byte after end_header
+    body_start: usize,
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, PlyError> {
+    let mut format = None;
+    let mut comments = Vec::new();
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+
+    for line_number in 1.. {
+        let Some(length) = data[offset..].iter().position(|&byte| byte == b'\n') else {
+            return Err(if line_number == 1 { PlyError::NotPly } else { PlyError::InvalidHeader { line: line_number } });
+        };
+        let line = String::from_utf8_lossy(&data[offset..offset + length]);
+        offset += length + 1;
+        let invalid = PlyError::InvalidHeader { line: line_number };
+
+        let mut tokens = line.split_whitespace();
+        match (line_number, tokens.next()) {
+            (1, Some("ply")) => {},
+            (1, _) => return Err(PlyError::NotPly),
+            (_, Some("format")) => {
+                format = Some(match tokens.next() {
+                    Some("ascii") => PlyFormat::Ascii,
+                    Some("binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                    Some("binary_big_endian") => PlyFormat::BinaryBigEndian,
+                    _ => return Err(invalid),
+                });
+            },
+            (_, Some("comment")) | (_, Some("obj_info")) => {
+                let text = line.trim_start().split_once(char::is_whitespace).map_or("", |(_, text)| text);
+                comments.push(text.trim().to_string());
+            },
+            (_, Some("element")) => {
+                let (Some(name), Some(count)) = (tokens.next(), tokens.next().and_then(|count| count.parse().ok())) else {
+                    return Err(invalid);
+                };
+                elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+            },
+            (_, Some("property")) => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(invalid);
+                };
+                let property = match tokens.next() {
+                    Some("list") => {
+                        let count = tokens.next().and_then(ScalarType::parse);
+                        let item = tokens.next().and_then(ScalarType::parse);
+                        match (count, item, tokens.next()) {
+                            (Some(count), Some(item), Some(name)) => Property::List { name: name.to_string(), count, item },
+                            _ => return Err(invalid),
+                        }
+                    },
+                    kind => match (kind.and_then(ScalarType::parse), tokens.next()) {
+                        (Some(kind), Some(name)) => Property::Scalar { name: name.to_string(), kind },
+                        _ => return Err(invalid),
+                    },
+                };
+                element.properties.push(property);
+            },
+            (_, Some("end_header")) => break,
+            (_, None) => {},
+            _ => return Err(invalid),
+        }
+    }
+
+    Ok(Header {
+        format: format.ok_or(PlyError::InvalidHeader { line: 2 })?,
+        comments,
+        elements,
+        body_start: offset,
+    })
+}
+
+// Reads the values of the body one at a time, whatever the encoding
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, kind: ScalarType) -> Option<f64> {
+        match self {
+            Body::Ascii(tokens) => tokens.next()?.parse().ok(),
+            Body::Binary { data, big_endian } => {
+                let size = kind.size();
+                if data.len() < size {
+                    return None;
+                }
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(&data[..size]);
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                *data = &data[size..];
+                Some(match kind {
+                    ScalarType::Int8 => bytes[0] as i8 as f64,
+                    ScalarType::UInt8 => bytes[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(bytes),
+                })
+            },
+        }
+    }
+}
+
+pub fn is_ply(sample: &[u8]) -> bool {
+    sample.starts_with(b"ply\n") || sample.starts_with(b"ply\r\n")
+}
+
+// Index of the first property with one of the given names
+fn find_property(element: &Element, names: &[&str]) -> Option<usize> {
+    element.properties.iter().position(|property| names.contains(&property.name()))
+}
+
+// Reads the vertex and face elements into a single mesh, skipping any
+// other elements and properties. Polygons are triangulated.
+pub fn load_ply(file_path: impl AsRef<Path>) -> Result<Scene, PlyError> {
+    let file_path = file_path.as_ref();
+    let mut file = File::open(file_path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => PlyError::NotFound(file_path.display().to_string()),
+        _ => PlyError::Io(e),
+    })?;
+    let name = file_path
+        .file_stem()
+        .map_or_else(|| file_path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let header = parse_header(&data)?;
+    let body = &data[header.body_start..];
+    let mut body = match header.format {
+        PlyFormat::Ascii => Body::Ascii(std::str::from_utf8(body)
+            .map_err(|_| PlyError::UnexpectedEnd { element: "ASCII".to_string() })?
+            .split_ascii_whitespace()),
+        PlyFormat::BinaryLittleEndian => Body::Binary { data: body, big_endian: false },
+        PlyFormat::BinaryBigEndian => Body::Binary { data: body, big_endian: true },
+    };
+
+    let mut mesh = TriMesh::new(&name);
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut faces: Vec<Vec<i64>> = Vec::new();
+    let mut values = Vec::new();
+    let mut list = Vec::new();
+
+    for element in &header.elements {
+        let position = [&["x"][..], &["y"], &["z"]].map(|names| find_property(element, names));
+        let normal = [&["nx"][..], &["ny"], &["nz"]].map(|names| find_property(element, names));
+        let color = [
+            &["red", "diffuse_red", "r"][..],
+            &["green", "diffuse_green", "g"],
+            &["blue", "diffuse_blue", "b"],
+        ].map(|names| find_property(element, names));
+        let indices = find_property(element, &["vertex_indices", "vertex_index"]);
+
+        for index in 0..element.count {
+            let end = || PlyError::UnexpectedEnd { element: element.name.clone() };
+            values.clear();
+            list.clear();
+            for (property_index, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar { kind, .. } => values.push(body.read(*kind).ok_or_else(end)?),
+                    Property::List { count, item, .. } => {
+                        values.push(0.0);
+                        let count = body.read(*count).ok_or_else(end)?;
+                        if count < 0.0 {
+                            return Err(PlyError::InvalidValue { element: element.name.clone(), index });
+                        }
+                        for _ in 0..count as usize {
+                            let value = body.read(*item).ok_or_else(end)?;
+                            if Some(property_index) == indices {
+                                list.push(value as i64);
+                            }
+                        }
+                    },
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [Some(x), Some(y), Some(z)] = position else {
+                        return Err(PlyError::InvalidValue { element: element.name.clone(), index });
+                    };
+                    let vertex = [x, y, z].map(|property| values[property] as f32);
+                    if vertex.iter().any(|v| !v.is_finite()) {
+                        return Err(PlyError::InvalidCoordinate { vertex: index });
+                    }
+                    mesh.positions.push(vertex);
+                    if let [Some(x), Some(y), Some(z)] = normal {
+                        normals.push([x, y, z].map(|property| values[property] as f32));
+                    }
+                    if let [Some(r), Some(g), Some(b)] = color {
+                        colors.push([r, g, b].map(|property| {
+                            let Property::Scalar { kind, .. } = element.properties[property] else {
+                                return 1.0;
+                            };
+                            (values[property] / kind.color_scale()).clamp(0.0, 1.0) as f32
+                        }));
+                    }
+                },
+                "face" => faces.push(list.clone()),
+                _ => {},
+            }
+        }
+    }
+
+    let vertex_count = mesh.positions.len() as i64;
+    let mut points = Vec::new();
+    for (face, polygon) in faces.iter().enumerate() {
+        if let Some(&index) = polygon.iter().find(|&&index| index < 0 || index >= vertex_count) {
+            return Err(PlyError::IndexOutOfRange { face, index });
+        }
+        points.clear();
+        points.extend(polygon.iter().map(|&index| mesh.positions[index as usize]));
+        for tri in triangulate_polygon(&points) {
+            mesh.triangles.push(tri.map(|corner| polygon[corner] as u32));
+        }
+    }
+
+    if !normals.is_empty() {
+        mesh.normals = Some(normals);
+    }
+    if !colors.is_empty() {
+        mesh.colors = Some(colors);
+    }
+
+    Ok(Scene {
+        header: header.comments.join("\n").into_bytes(),
+        meshes: vec![mesh],
+    })
+}
+
+// PLY holds a single mesh, so the scene's meshes are merged. Normals and
+// colors are written when every mesh has them, colors as 8-bit RGB.
+pub fn save_ply(scene: &Scene, file_path: impl AsRef<Path>, format: PlyFormat) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut mesh = TriMesh::default();
+    for part in &scene.meshes {
+        mesh.append(part);
+    }
+
+    let mut file = BufWriter::new(File::create(file_path)?);
+    writeln!(file, "ply")?;
+    writeln!(file, "format {} 1.0", match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        PlyFormat::BinaryBigEndian => "binary_big_endian",
+    })?;
+    let header = String::from_utf8_lossy(&scene.header);
+    for line in header.trim_end_matches('\0').lines() {
+        writeln!(file, "comment {}", line.trim_end_matches('\0'))?;
+    }
+    writeln!(file, "element vertex {}", mesh.positions.len())?;
+    writeln!(file, "property float x\nproperty float y\nproperty float z")?;
+    if mesh.normals.is_some() {
+        writeln!(file, "property float nx\nproperty float ny\nproperty float nz")?;
+    }
+    if mesh.colors.is_some() {
+        writeln!(file, "property uchar red\nproperty uchar green\nproperty uchar blue")?;
+    }
+    writeln!(file, "element face {}", mesh.num_triangles())?;
+    writeln!(file, "property list uchar int vertex_indices")?;
+    writeln!(file, "end_header")?;
+
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for index in 0..mesh.positions.len() {
+        let mut floats = mesh.positions[index].to_vec();
+        if let Some(normals) = &mesh.normals {
+            floats.extend_from_slice(&normals[index]);
+        }
+        let color = mesh.colors.as_ref().map(|colors| colors[index].map(to_byte));
+
+        match format {
+            PlyFormat::Ascii => {
+                let mut fields: Vec<String> = floats.iter().map(f32::to_string).collect();
+                if let Some(color) = color {
+                    fields.extend(color.iter().map(u8::to_string));
+                }
+                writeln!(file, "{}", fields.join(" "))?;
+            },
+            PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => {
+                for value in floats {
+                    let bytes = match format {
+                        PlyFormat::BinaryBigEndian => value.to_be_bytes(),
+                        _ => value.to_le_bytes(),
+                    };
+                    file.write_all(&bytes)?;
+                }
+                if let Some(color) = color {
+                    file.write_all(&color)?;
+                }
+            },
+        }
+    }
+
+    for tri in &mesh.triangles {
+        match format {
+            PlyFormat::Ascii => writeln!(file, "3 {} {} {}", tri[0], tri[1], tri[2])?,
+            PlyFormat::BinaryLittleEndian | PlyFormat::BinaryBigEndian => {
+                file.write_all(&[3])?;
+                for &index in tri {
+                    let bytes = match format {
+                        PlyFormat::BinaryBigEndian => (index as i32).to_be_bytes(),
+                        _ => (index as i32).to_le_bytes(),
+                    };
+                    file.write_all(&bytes)?;
+                }
+            },
+        }
+    }
+
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+
+    const ASCII_QUAD: &str = "ply
+format ascii 1.0
+comment made by hand
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float confidence
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255 0 0 0.5
+1 0 0 0 255 0 0.5
+1 1 0 0 0 255 0.5
+0 1 0 255 255 255 0.5
+4 0 1 2 3
+0 2
+";
+
+    #[test]
+    fn loads_ascii_with_colors_and_extra_elements() {
+        let path = temp_path("quad.ply");
+        std::fs::write(&path, ASCII_QUAD).unwrap();
+        let scene = load_ply(&path).unwrap();
+        assert_eq!(scene.header, b"made by hand");
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.num_triangles(), 2);
+        assert!((mesh.surface_area() - 1.0).abs() < 1e-9);
+        assert_eq!(mesh.colors.as_ref().unwrap()[..3], [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(mesh.normals, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saves_and_loads_back_in_every_format() {
+        let mut mesh = cube("cube", [-1.0; 3], [0.5, 2.0, 3.25]);
+        mesh.normals = Some(mesh.positions.clone());
+        mesh.colors = Some((0..mesh.positions.len()).map(|index| [index as f32 / 7.0, 0.0, 1.0]).collect());
+        let scene = Scene { header: b"one\ntwo".to_vec(), meshes: vec![mesh] };
+
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian] {
+            let path = temp_path(&format!("round_trip_{:?}.ply", format));
+            save_ply(&scene, &path, format).unwrap();
+            let loaded = load_ply(&path).unwrap();
+            let (loaded, saved) = (&loaded.meshes[0], &scene.meshes[0]);
+            assert_eq!(loaded.positions, saved.positions, "{:?}", format);
+            assert_eq!(loaded.triangles, saved.triangles, "{:?}", format);
+            assert_eq!(loaded.normals, saved.normals, "{:?}", format);
+            // Colors go through 8 bits
+            for (loaded, saved) in loaded.colors.as_ref().unwrap().iter().zip(saved.colors.as_ref().unwrap()) {
+                assert!((0..3).all(|i| (loaded[i] - saved[i]).abs() <= 0.5 / 255.0), "{:?}", format);
+            }
+            assert_eq!(load_ply(&path).unwrap().header, scene.header);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn load_error(name: &str, contents: &[u8]) -> PlyError {
+        let path = temp_path(name);
+        std::fs::write(&path, contents).unwrap();
+        let error = load_ply(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        error
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let error = load_error("not_ply.ply", b"solid cube\n");
+        assert!(matches!(error, PlyError::NotPly), "{:?}", error);
+        let error = load_error("bad_header.ply", ASCII_QUAD.replace("property float z", "property quad z").as_bytes());
+        assert!(matches!(error, PlyError::InvalidHeader { line: 7 }), "{:?}", error);
+        let error = load_error("out_of_range.ply", ASCII_QUAD.replace("4 0 1 2 3", "4 0 1 2 9").as_bytes());
+        assert!(matches!(error, PlyError::IndexOutOfRange { face: 0, index: 9 }), "{:?}", error);
+        let error = load_error("nan.ply", ASCII_QUAD.replace("1 1 0 0 0 255", "1 nan 0 0 0 255").as_bytes());
+        assert!(matches!(error, PlyError::InvalidCoordinate { vertex: 2 }), "{:?}", error);
+        let error = load_error("truncated.ply", ASCII_QUAD.replace("4 0 1 2 3\n0 2\n", "4 0 1").as_bytes());
+        assert!(matches!(&error, PlyError::UnexpectedEnd { element } if element == "face"), "{:?}", error);
+    }
+}
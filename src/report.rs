@@ -0,0 +1,235 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::cavity::{find_cavities, ShellReport};
+use crate::cleanup::{clean, CleanupReport};
+use crate::mesh::{Bounds, Scene, TriMesh};
+use crate::orient::orient;
+use crate::shell::extract_shells;
+use crate::topology::Topology;
+use crate::union::{self_union, UnionError, UnionReport};
+use crate::weld::weld;
+
+#[derive(Serialize)]
+pub struct AnalysisReport {
+    pub file: String,
+    pub meshes: Vec<MeshReport>,
+}
+
+#[derive(Serialize)]
+pub struct MeshReport {
+    pub name: String,
+    // Everything up to the shells is about the welded file, defects and all
+    pub triangles: usize,
+    pub vertices: usize,
+    pub unwelded_vertices: usize,
+    pub bounds: Option<Bounds>,
+    pub volume: f64,
+    pub surface_area: f64,
+    pub boundary_edges: usize,
+    pub non_manifold_edges: usize,
+    pub degenerate_triangles: usize,
+    pub cavities: usize,
+    pub shells: Vec<ShellSummary>,
+    pub cleanup: CleanupReport,
+    pub union: UnionReport,
+    // The mesh the fill command goes on to work with
+    pub prepared: PreparedSummary,
+}
+
+// After cleanup, orientation and merging overlapping parts
+#[derive(Serialize)]
+pub struct PreparedSummary {
+    pub triangles: usize,
+    pub vertices: usize,
+    pub volume: f64,
+    pub surface_area: f64,
+    pub boundary_edges: usize,
+    pub non_manifold_edges: usize,
+    pub cavities: usize,
+    pub shells: Vec<ShellSummary>,
+}
+
+#[derive(Serialize)]
+pub struct ShellSummary {
+    #[serde(flatten)]
+    pub classification: ShellReport,
+    pub triangles: usize,
+    pub signed_volume: f64,
+    pub surface_area: f64,
+}
+
+// Everything we know about a file without modifying it. The counts are
+// taken on the welded file, so they show what's wrong with it, and again
+// on the mesh cleanup, orientation and merging make of it, which is what
+// filling works with.
+pub fn analyze(file: &str, scene: &Scene, weld_epsilon: f32) -> Result<AnalysisReport, UnionError> {
+    let meshes = scene.meshes.iter()
+        .map(|mesh| {
+            let welded = weld(mesh, weld_epsilon);
+            let (cleaned, cleanup) = clean(&welded);
+            let (prepared, union) = self_union(&orient(&cleaned).0)?;
+            let (boundary_edges, non_manifold_edges) = edge_counts(&welded);
+            let (cavities, shells) = shell_summaries(&welded);
+            let (prepared_boundary_edges, prepared_non_manifold_edges) = edge_counts(&prepared);
+            let (prepared_cavities, prepared_shells) = shell_summaries(&prepared);
+
+            Ok(MeshReport {
+                name: mesh.name.clone(),
+                triangles: welded.num_triangles(),
+                vertices: welded.positions.len(),
+                unwelded_vertices: mesh.positions.len(),
+                bounds: welded.bounds(),
+                volume: welded.signed_volume(),
+                surface_area: welded.surface_area(),
+                boundary_edges,
+                non_manifold_edges,
+                // Counted on the file as loaded, welding and cleanup drop them
+                degenerate_triangles: (0..mesh.num_triangles()).filter(|&index| mesh.is_degenerate(index)).count(),
+                cavities,
+                shells,
+                cleanup,
+                union,
+                prepared: PreparedSummary {
+                    triangles: prepared.num_triangles(),
+                    vertices: prepared.positions.len(),
+                    volume: prepared.signed_volume(),
+                    surface_area: prepared.surface_area(),
+                    boundary_edges: prepared_boundary_edges,
+                    non_manifold_edges: prepared_non_manifold_edges,
+                    cavities: prepared_cavities,
+                    shells: prepared_shells,
+                },
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(AnalysisReport { file: file.to_string(), meshes })
+}
+
+fn edge_counts(mesh: &TriMesh) -> (usize, usize) {
+    let topology = Topology::new(mesh);
+    (
+        topology.edges.iter().filter(|edge| edge.is_boundary()).count(),
+        topology.edges.iter().filter(|edge| edge.is_non_manifold()).count(),
+    )
+}
+
+fn shell_summaries(mesh: &TriMesh) -> (usize, Vec<ShellSummary>) {
+    let shells = extract_shells(mesh);
+    let cavities = find_cavities(&shells);
+    let summaries = shells.iter()
+        .zip(cavities.shells.iter().cloned())
+        .map(|(shell, classification)| ShellSummary {
+            classification,
+            triangles: shell.stats.num_triangles,
+            signed_volume: shell.stats.signed_volume,
+            surface_area: shell.stats.surface_area,
+        })
+        .collect();
+    (cavities.cavities().count(), summaries)
+}
+
+impl fmt::Display for AnalysisReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.file)?;
+        for mesh in &self.meshes {
+            writeln!(f, "{}", mesh.name)?;
+            writeln!(f, "  triangles: {}", mesh.triangles)?;
+            writeln!(f, "  vertices: {} ({} before welding)", mesh.vertices, mesh.unwelded_vertices)?;
+            if let Some(bounds) = mesh.bounds {
+                writeln!(f, "  bounds: {:?} to {:?}", bounds.min, bounds.max)?;
+                writeln!(f, "  size: {:?}", bounds.size())?;
+            }
+            writeln!(f, "  volume: {:.3}", mesh.volume)?;
+            writeln!(f, "  surface area: {:.3}", mesh.surface_area)?;
+            writeln!(f, "  boundary edges: {}", mesh.boundary_edges)?;
+            writeln!(f, "  non-manifold edges: {}", mesh.non_manifold_edges)?;
+            writeln!(f, "  degenerate triangles: {}", mesh.degenerate_triangles)?;
+            write_shells(f, "  ", mesh.cavities, &mesh.shells)?;
+            write!(f, "  cleanup: {}", mesh.cleanup)?;
+            write!(f, "  union: {}", mesh.union)?;
+
+            let prepared = &mesh.prepared;
+            writeln!(
+                f,
+                "  prepared for filling: {} triangles, {} vertices, volume {:.3}, surface area {:.3}, {} boundary edges, {} non-manifold edges",
+                prepared.triangles,
+                prepared.vertices,
+                prepared.volume,
+                prepared.surface_area,
+                prepared.boundary_edges,
+                prepared.non_manifold_edges
+            )?;
+            write_shells(f, "    ", prepared.cavities, &prepared.shells)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_shells(f: &mut fmt::Formatter<'_>, indent: &str, cavities: usize, shells: &[ShellSummary]) -> fmt::Result {
+    writeln!(f, "{}{} shells, {} cavities", indent, shells.len(), cavities)?;
+    for shell in shells {
+        writeln!(
+            f,
+            "{}  {} ({:?}): {} triangles, volume {:.3}, surface area {:.3}",
+            indent,
+            shell.classification.name,
+            shell.classification.kind,
+            shell.triangles,
+            shell.signed_volume,
+            shell.surface_area
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, inverted};
+
+    #[test]
+    fn reports_on_the_file_and_on_what_filling_sees() {
+        let mut hollow = cube("hollow", [0.0; 3], [10.0; 3]);
+        hollow.append(&cube("hollow", [3.0; 3], [7.0; 3]));
+        let scene = Scene { header: Vec::new(), meshes: vec![inverted(cube("cube", [0.0; 3], [10.0; 3])), hollow] };
+        let report = analyze("scene", &scene, 0.0).unwrap();
+
+        let [solid, hollow] = &report.meshes[..] else { panic!("expected two meshes") };
+        // Inside out in the file, turned the right way out for filling
+        assert_eq!(solid.triangles, 12);
+        assert!((solid.volume + 1000.0).abs() < 1e-6);
+        assert!((solid.prepared.volume - 1000.0).abs() < 1e-6);
+        assert_eq!((solid.cavities, solid.prepared.cavities), (0, 0));
+        // The cavity is wound like the outside until it's oriented
+        assert_eq!(hollow.triangles, 24);
+        assert!((hollow.volume - 1064.0).abs() < 1e-6);
+        assert!((hollow.prepared.volume - 936.0).abs() < 1e-6);
+        assert_eq!((hollow.cavities, hollow.prepared.cavities), (1, 1));
+        assert_eq!(hollow.union.removed_triangles, 0);
+    }
+
+    #[test]
+    fn reports_defects_cleanup_removes() {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        mesh.triangles.push(mesh.triangles[0]);
+        let report = analyze("cube", &Scene { header: Vec::new(), meshes: vec![mesh] }, 0.0).unwrap();
+        let mesh = &report.meshes[0];
+        assert_eq!((mesh.triangles, mesh.non_manifold_edges), (13, 3));
+        assert_eq!((mesh.prepared.triangles, mesh.prepared.non_manifold_edges, mesh.prepared.boundary_edges), (12, 0, 0));
+    }
+
+    #[test]
+    fn keeps_degenerate_triangles_at_the_top_level() {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        mesh.triangles.push([0, 1, 1]);
+        let report = analyze("cube", &Scene { header: Vec::new(), meshes: vec![mesh] }, 0.0).unwrap();
+        assert_eq!(report.meshes[0].degenerate_triangles, 1);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["meshes"][0]["degenerate_triangles"], 1);
+        assert!(json["meshes"][0]["cleanup"].is_object());
+    }
+}
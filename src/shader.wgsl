@@ -32,5 +32,8 @@ fn vs_main(
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.normal, 1.0);
+    // Fixed light over the viewer's shoulder, enough to read the shape
+    let light = normalize(vec3<f32>(0.4, 0.8, 0.6));
+    let shade = 0.3 + 0.7 * max(dot(normalize(in.normal), light), 0.0);
+    return vec4<f32>(in.color * shade, 1.0);
 }
\ No newline at end of file
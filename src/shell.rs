@@ -0,0 +1,150 @@
+use crate::mesh::{Bounds, TriMesh};
+use crate::topology::Topology;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShellStats {
+    pub num_triangles: usize,
+    pub bounds: Bounds,
+    pub signed_volume: f64,
+    pub surface_area: f64,
+}
+
+pub struct Shell {
+    pub mesh: TriMesh,
+    pub stats: ShellStats,
+}
+
+impl ShellStats {
+    pub fn new(mesh: &TriMesh) -> Self {
+        Self {
+            num_triangles: mesh.num_triangles(),
+            bounds: mesh.bounds().unwrap_or(Bounds { min: [0.0; 3], max: [0.0; 3] }),
+            signed_volume: mesh.signed_volume(),
+            surface_area: mesh.surface_area(),
+        }
+    }
+}
+
+// Splits a welded mesh into its edge-connected components, largest surface
+// area first.
+// Shells that only touch at a vertex stay separate.
+pub fn extract_shells(mesh: &TriMesh) -> Vec<Shell> {
+    let mut shells: Vec<Shell> = shell_faces(mesh).iter()
+        .map(|faces| {
+            let shell_mesh = submesh(mesh, faces);
+            Shell {
+                stats: ShellStats::new(&shell_mesh),
+                mesh: shell_mesh,
+            }
+        })
+        .collect();
+    shells.sort_by(|a, b| b.stats.surface_area.total_cmp(&a.stats.surface_area));
+    for (index, shell) in shells.iter_mut().enumerate() {
+        shell.mesh.name = format!("{} shell {}", mesh.name, index);
+    }
+    shells
+}
+
+// Faces of each edge-connected component, in the order they're found
+pub fn shell_faces(mesh: &TriMesh) -> Vec<Vec<u32>> {
+    let topology = Topology::new(mesh);
+    let mut shell_of: Vec<Option<usize>> = vec![None; mesh.num_triangles()];
+    let mut shell_faces: Vec<Vec<u32>> = Vec::new();
+
+    for start in 0..mesh.num_triangles() {
+        if shell_of[start].is_some() {
+            continue;
+        }
+
+        let shell = shell_faces.len();
+        let mut faces = vec![start as u32];
+        shell_of[start] = Some(shell);
+        let mut next = 0;
+        while next < faces.len() {
+            let face = faces[next];
+            next += 1;
+            for neighbour in topology.face_neighbours(face) {
+                if shell_of[neighbour as usize].is_none() {
+                    shell_of[neighbour as usize] = Some(shell);
+                    faces.push(neighbour);
+                }
+            }
+        }
+        shell_faces.push(faces);
+    }
+    shell_faces
+}
+
+// Copies the given faces into a new mesh with only the vertices they use
+pub fn submesh(mesh: &TriMesh, faces: &[u32]) -> TriMesh {
+    let mut sub = TriMesh::new(&mesh.name);
+    let mut remap: Vec<Option<u32>> = vec![None; mesh.positions.len()];
+    let mut normals = mesh.normals.as_ref().map(|_| Vec::new());
+    let mut colors = mesh.colors.as_ref().map(|_| Vec::new());
+    let mut face_colors = mesh.face_colors.as_ref().map(|_| Vec::new());
+
+    for &face in faces {
+        let tri = mesh.triangles[face as usize].map(|index| {
+            *remap[index as usize].get_or_insert_with(|| {
+                sub.positions.push(mesh.positions[index as usize]);
+                if let (Some(normals), Some(source)) = (normals.as_mut(), mesh.normals.as_ref()) {
+                    normals.push(source[index as usize]);
+                }
+                if let (Some(colors), Some(source)) = (colors.as_mut(), mesh.colors.as_ref()) {
+                    colors.push(source[index as usize]);
+                }
+                sub.positions.len() as u32 - 1
+            })
+        });
+        sub.triangles.push(tri);
+        if let (Some(face_colors), Some(source)) = (face_colors.as_mut(), mesh.face_colors.as_ref()) {
+            face_colors.push(source[face as usize]);
+        }
+    }
+
+    sub.normals = normals;
+    sub.colors = colors;
+    sub.face_colors = face_colors;
+    sub
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, inverted};
+
+    #[test]
+    fn splits_disjoint_and_nested_cubes() {
+        let mut mesh = cube("parts", [0.0; 3], [10.0; 3]);
+        mesh.append(&cube("parts", [20.0; 3], [22.0; 3]));
+        mesh.append(&inverted(cube("parts", [3.0; 3], [7.0; 3])));
+        let shells = extract_shells(&mesh);
+
+        // Largest surface first
+        let expected = [
+            ([0.0; 3], [10.0; 3], 1000.0, 600.0),
+            ([3.0; 3], [7.0; 3], -64.0, 96.0),
+            ([20.0; 3], [22.0; 3], 8.0, 24.0),
+        ];
+        assert_eq!(shells.len(), expected.len());
+        for (index, (shell, (min, max, volume, area))) in shells.iter().zip(expected).enumerate() {
+            assert_eq!(shell.mesh.name, format!("parts shell {}", index));
+            assert_eq!(shell.stats.num_triangles, 12);
+            assert_eq!(shell.mesh.positions.len(), 8);
+            assert_eq!((shell.stats.bounds.min, shell.stats.bounds.max), (min, max));
+            assert!((shell.stats.signed_volume - volume).abs() < 1e-6, "shell {} volume {}", index, shell.stats.signed_volume);
+            assert!((shell.stats.surface_area - area).abs() < 1e-6, "shell {} area {}", index, shell.stats.surface_area);
+        }
+    }
+
+    #[test]
+    fn keeps_shells_touching_at_a_corner_apart() {
+        let mut mesh = cube("corner", [0.0; 3], [1.0; 3]);
+        let offset = mesh.positions.len() as u32;
+        let other = cube("corner", [1.0; 3], [2.0; 3]);
+        mesh.positions.extend(&other.positions[1..]);
+        // Share the first cube's far corner instead of a copy of it
+        mesh.triangles.extend(other.triangles.iter().map(|tri| tri.map(|v| if v == 0 { 7 } else { v - 1 + offset })));
+        assert_eq!(shell_faces(&mesh).len(), 2);
+    }
+}
@@ -0,0 +1,548 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::mesh::{Scene, TriMesh};
+
+fn f32_from_vec_offset(vec: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes([
+        vec[offset],
+        vec[offset + 1],
+        vec[offset + 2],
+        vec[offset + 3]
+    ])
+}
+
+#[derive(Debug)]
+pub enum StlError {
+    NotFound(String),
+    TruncatedHeader { file_len: u64 },
+    TriangleCountMismatch { num_tris: u32, file_len: u64 },
+    InvalidCoordinate { triangle: usize },
+    InvalidAscii { line: usize },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for StlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StlError::NotFound(path) => write!(f, "{} does not exist", path),
+            StlError::TruncatedHeader { file_len } => write!(
+                f, "file is {} bytes, too short for a binary STL header", file_len),
+            StlError::TriangleCountMismatch { num_tris, file_len } => write!(
+                f, "header declares {} triangles ({} bytes) but the file is {} bytes",
+                num_tris, STL_HEADER_SIZE + 4 + *num_tris as u64 * STL_TRIANGLE_SIZE, file_len),
+            StlError::InvalidCoordinate { triangle } => write!(
+                f, "triangle {} has a NaN or infinite coordinate", triangle),
+            StlError::InvalidAscii { line } => write!(f, "malformed ASCII STL on line {}", line),
+            StlError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StlError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StlError {
+    fn from(e: std::io::Error) -> Self {
+        StlError::Io(e)
+    }
+}
+
+const STL_HEADER_SIZE: u64 = 80;
+const STL_TRIANGLE_SIZE: u64 = (12 * 4) + 2;
+// How much of the file to look at when deciding between ASCII and binary
+const STL_SNIFF_SIZE: u64 = 512;
+// Facets per parallel work item, small enough to balance and big enough
+// that splitting doesn't dominate
+const STL_PARALLEL_CHUNK: usize = 4096;
+
+// Bit 15 of the attribute word, set by both color conventions
+const STL_COLOR_FLAG: u16 = 0x8000;
+
+// Both conventions pack 5 bits per channel into the attribute word, but in
+// opposite channel orders and with bit 15 meaning opposite things
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorConvention {
+    // VisCAM/SolidView: blue in the low bits, bit 15 set if the facet has a color
+    VisCam,
+    // Materialise Magics: red in the low bits, bit 15 set if the facet
+    // uses the default color from the header's COLOR= field
+    Materialise,
+}
+
+impl ColorConvention {
+    fn from_header(header: &[u8]) -> Self {
+        if header.windows(6).any(|window| window == b"COLOR=") {
+            ColorConvention::Materialise
+        } else {
+            ColorConvention::VisCam
+        }
+    }
+
+    // None for facets without a color of their own, which the header's
+    // default covers for Materialise
+    fn decode(self, attribute: u16) -> Option<[f32; 3]> {
+        let channel = |shift: u16| ((attribute >> shift) & 0x1f) as f32 / 31.0;
+        let flagged = attribute & STL_COLOR_FLAG != 0;
+        match self {
+            ColorConvention::VisCam => flagged.then(|| [channel(10), channel(5), channel(0)]),
+            ColorConvention::Materialise => (!flagged).then(|| [channel(0), channel(5), channel(10)]),
+        }
+    }
+
+    fn encode(self, color: Option<[f32; 3]>) -> u16 {
+        let channel = |value: f32, shift: u16| ((value.clamp(0.0, 1.0) * 31.0).round() as u16) << shift;
+        match (self, color) {
+            (ColorConvention::VisCam, Some([r, g, b])) => STL_COLOR_FLAG | channel(r, 10) | channel(g, 5) | channel(b, 0),
+            (ColorConvention::VisCam, None) => 0,
+            (ColorConvention::Materialise, Some([r, g, b])) => channel(r, 0) | channel(g, 5) | channel(b, 10),
+            (ColorConvention::Materialise, None) => STL_COLOR_FLAG,
+        }
+    }
+}
+
+// STL is a triangle soup, every facet gets its own three vertices
+fn push_triangle(
+    mesh: &mut TriMesh,
+    face_normals: &mut Vec<[f32; 3]>,
+    normal: [f32; 3],
+    positions: [[f32; 3]; 3],
+) -> Result<(), StlError> {
+    if positions.iter().flatten().any(|v| !v.is_finite()) {
+        return Err(StlError::InvalidCoordinate { triangle: mesh.triangles.len() });
+    }
+
+    let current_index = mesh.positions.len() as u32;
+    mesh.positions.extend_from_slice(&positions);
+    face_normals.push(normal);
+    mesh.triangles.push([current_index, current_index + 1, current_index + 2]);
+    Ok(())
+}
+
+// Binary headers are free-form and plenty of exporters start them with "solid",
+// so the prefix alone isn't enough. A binary file whose length matches its
+// triangle count wins, otherwise the start of the file has to look like text.
+fn is_ascii_stl(sample: &[u8], file_len: u64) -> bool {
+    if is_binary_stl(sample, file_len) {
+        return false;
+    }
+
+    let text = sample.trim_ascii_start();
+    text.starts_with(b"solid")
+        && text.iter().all(|b| !b.is_ascii_control() || b.is_ascii_whitespace())
+}
+
+// The triangle count in the header accounts for every byte of the file
+fn is_binary_stl(sample: &[u8], file_len: u64) -> bool {
+    let offset = STL_HEADER_SIZE as usize;
+    let Some(count) = sample.get(offset..offset + 4) else {
+        return false;
+    };
+    let num_tris = u32::from_le_bytes(count.try_into().unwrap());
+    STL_HEADER_SIZE + 4 + num_tris as u64 * STL_TRIANGLE_SIZE == file_len
+}
+
+// Whether the start of a file looks like either flavour of STL
+pub fn is_stl(sample: &[u8], file_len: u64) -> bool {
+    is_binary_stl(sample, file_len) || is_ascii_stl(sample, file_len)
+}
+
+fn read_binary_stl(
+    data: &[u8],
+    mesh: &mut TriMesh,
+    face_normals: &mut Vec<[f32; 3]>,
+) -> Result<Vec<u8>, StlError> {
+    let file_len = data.len() as u64;
+    if file_len < STL_HEADER_SIZE + 4 {
+        return Err(StlError::TruncatedHeader { file_len });
+    }
+
+    // Read the file header -> 80 bytes
+    // Read the number of triangles -> u32
+    let header = &data[..STL_HEADER_SIZE as usize];
+    let num_tris = u32::from_le_bytes(data[STL_HEADER_SIZE as usize..][..4].try_into().unwrap());
+
+    if STL_HEADER_SIZE + 4 + num_tris as u64 * STL_TRIANGLE_SIZE != file_len {
+        return Err(StlError::TriangleCountMismatch { num_tris, file_len });
+    }
+
+    // Every facet is a fixed size record, so the output can be allocated
+    // up front and filled from the byte slice in parallel
+    let num_tris = num_tris as usize;
+    let f32_size = std::mem::size_of::<f32>();
+    let read_vec3 = |buffer: &[u8], offset: usize| [
+        f32_from_vec_offset(buffer, offset),
+        f32_from_vec_offset(buffer, offset + f32_size),
+        f32_from_vec_offset(buffer, offset + 2 * f32_size)
+    ];
+    let mut positions = vec![[0.0; 3]; num_tris * 3];
+    let mut facet_normals = vec![[0.0; 3]; num_tris];
+    let mut attributes = vec![0u16; num_tris];
+    positions.par_chunks_mut(3)
+        .zip(facet_normals.par_iter_mut())
+        .zip(attributes.par_iter_mut())
+        .zip(data[STL_HEADER_SIZE as usize + 4..].par_chunks_exact(STL_TRIANGLE_SIZE as usize))
+        .with_min_len(STL_PARALLEL_CHUNK)
+        .for_each(|(((positions, normal), attribute), facet)| {
+            *normal = read_vec3(facet, 0);
+            for (corner, position) in positions.iter_mut().enumerate() {
+                *position = read_vec3(facet, (corner + 1) * 3 * f32_size);
+            }
+            *attribute = u16::from_le_bytes([facet[48], facet[49]]);
+        });
+
+    let invalid = positions.par_chunks(3)
+        .with_min_len(STL_PARALLEL_CHUNK)
+        .position_first(|positions| positions.iter().flatten().any(|v| !v.is_finite()));
+    if let Some(triangle) = invalid {
+        return Err(StlError::InvalidCoordinate { triangle });
+    }
+
+    mesh.positions = positions;
+    mesh.triangles = (0..num_tris as u32)
+        .into_par_iter()
+        .map(|index| [index * 3, index * 3 + 1, index * 3 + 2])
+        .collect();
+    *face_normals = facet_normals;
+
+    let convention = ColorConvention::from_header(header);
+    if attributes.par_iter().any(|&attribute| convention.decode(attribute).is_some()) {
+        mesh.face_colors = Some(attributes.par_iter().map(|&attribute| convention.decode(attribute)).collect());
+    }
+
+    Ok(header.to_vec())
+}
+
+fn parse_vec3<'a>(tokens: &mut impl Iterator<Item = &'a str>, line: usize) -> Result<[f32; 3], StlError> {
+    let mut value = [0.0; 3];
+    for component in value.iter_mut() {
+        *component = tokens.next()
+            .and_then(|token| token.parse().ok())
+            .ok_or(StlError::InvalidAscii { line })?;
+    }
+    Ok(value)
+}
+
+// solid <name>
+//   facet normal nx ny nz
+//     outer loop
+//       vertex x y z
+//       ...
+//     endloop
+//   endfacet
+// endsolid <name>
+fn read_ascii_stl(
+    data: &[u8],
+    mesh: &mut TriMesh,
+    face_normals: &mut Vec<[f32; 3]>,
+) -> Result<Vec<u8>, StlError> {
+    let contents = String::from_utf8_lossy(data);
+
+    let mut header = Vec::new();
+    let mut normal = [0.0; 3];
+    let mut facet: Vec<[f32; 3]> = Vec::with_capacity(3);
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line_number = line_index + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            // Keep the name of the first solid so it survives a binary save
+            Some("solid") if mesh.triangles.is_empty() => {
+                header = line.trim_start()["solid".len()..].trim().as_bytes().to_vec();
+            },
+            Some("facet") => {
+                // facet normal nx ny nz
+                tokens.next();
+                normal = parse_vec3(&mut tokens, line_number)?;
+                facet.clear();
+            },
+            Some("vertex") => facet.push(parse_vec3(&mut tokens, line_number)?),
+            Some("endloop") => {
+                if facet.len() < 3 {
+                    return Err(StlError::InvalidAscii { line: line_number });
+                }
+                // Some exporters write polygons, fan them out into triangles
+                for i in 1..facet.len() - 1 {
+                    push_triangle(mesh, face_normals, normal, [facet[0], facet[i], facet[i + 1]])?;
+                }
+                facet.clear();
+            },
+            _ => {}
+        }
+    }
+
+    Ok(header)
+}
+
+// The file is memory mapped rather than read, so multi-gigabyte files are
+// parsed straight out of the page cache without a copy
+pub fn load_stl(file_path: impl AsRef<Path>) -> Result<Scene, StlError> {
+    let file_path = file_path.as_ref();
+    let file = File::open(file_path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => StlError::NotFound(file_path.display().to_string()),
+        _ => StlError::Io(e),
+    })?;
+    // Safety: the map is only read while loading, and like every other
+    // reader we can't do anything sensible if the file changes underneath us
+    let data = unsafe { Mmap::map(&file)? };
+    let file_len = data.len() as u64;
+    let sample = &data[..data.len().min(STL_SNIFF_SIZE as usize)];
+
+    let name = file_path
+        .file_stem()
+        .map_or_else(|| file_path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+    let mut mesh = TriMesh::new(&name);
+    let mut face_normals = Vec::new();
+    let header = if is_ascii_stl(sample, file_len) {
+        read_ascii_stl(&data, &mut mesh, &mut face_normals)?
+    } else {
+        read_binary_stl(&data, &mut mesh, &mut face_normals)?
+    };
+    mesh.face_normals = Some(face_normals);
+
+    Ok(Scene {
+        header,
+        meshes: vec![mesh],
+    })
+}
+
+// Normals are always recomputed from the winding rather than trusting
+// whatever the mesh carries, so every facet written is self consistent.
+pub fn save_stl(scene: &Scene, file_path: impl AsRef<Path>) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let facets = facet_count(scene.num_triangles())?;
+    let mut header = [0u8; STL_HEADER_SIZE as usize];
+    let len = scene.header.len().min(header.len());
+    header[..len].copy_from_slice(&scene.header[..len]);
+
+    let mut file = BufWriter::new(File::create(file_path)?);
+    file.write_all(&header)?;
+    file.write_all(&facets.to_le_bytes())?;
+
+    // Keep whichever color convention the header says the file came with
+    let convention = ColorConvention::from_header(&header);
+    for mesh in &scene.meshes {
+        for index in 0..mesh.num_triangles() {
+            let [pos1, pos2, pos3] = mesh.triangle(index);
+            let color = match (&mesh.face_colors, &mesh.colors) {
+                (Some(face_colors), _) => face_colors[index],
+                (None, Some(colors)) => {
+                    let corners = mesh.triangles[index].map(|corner| colors[corner as usize]);
+                    Some([0, 1, 2].map(|i| corners.iter().map(|color| color[i]).sum::<f32>() / 3.0))
+                },
+                (None, None) => None,
+            };
+            let attribute = convention.encode(color);
+
+            for value in mesh.facet_normal(index).iter()
+                .chain(pos1.iter())
+                .chain(pos2.iter())
+                .chain(pos3.iter())
+            {
+                file.write_all(&value.to_le_bytes())?;
+            }
+            file.write_all(&attribute.to_le_bytes())?;
+        }
+    }
+
+    file.flush()
+}
+
+// Binary STL counts facets in 32 bits, checked before anything is written
+fn facet_count(triangles: usize) -> std::io::Result<u32> {
+    u32::try_from(triangles).map_err(|_| std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{} triangles is more than binary STL can hold", triangles),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+
+    fn attributes(path: &Path) -> Vec<u16> {
+        let data = std::fs::read(path).unwrap();
+        data[STL_HEADER_SIZE as usize + 4..]
+            .chunks_exact(STL_TRIANGLE_SIZE as usize)
+            .map(|facet| u16::from_le_bytes([facet[48], facet[49]]))
+            .collect()
+    }
+
+    fn partly_colored(header: &[u8]) -> Scene {
+        let mut mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        let mut colors = vec![None; mesh.num_triangles()];
+        colors[0] = Some([1.0, 0.0, 0.0]);
+        colors[5] = Some([0.0, 0.0, 1.0]);
+        mesh.face_colors = Some(colors);
+        Scene { header: header.to_vec(), meshes: vec![mesh] }
+    }
+
+    #[test]
+    fn viscam_colors_round_trip() {
+        let scene = partly_colored(b"binary");
+        let (first, second) = (temp_path("viscam_first.stl"), temp_path("viscam_second.stl"));
+        save_stl(&scene, &first).unwrap();
+        let loaded = load_stl(&first).unwrap();
+        assert_eq!(loaded.meshes[0].face_colors, scene.meshes[0].face_colors);
+
+        save_stl(&loaded, &second).unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), std::fs::read(&second).unwrap());
+        let words = attributes(&first);
+        assert_eq!(words[0], 0x8000 | 0x1f << 10);
+        assert_eq!(words[1], 0);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn materialise_colors_round_trip() {
+        let scene = partly_colored(b"COLOR=\xff\x80\x00\xff MATERIAL=");
+        let (first, second) = (temp_path("materialise_first.stl"), temp_path("materialise_second.stl"));
+        save_stl(&scene, &first).unwrap();
+        let loaded = load_stl(&first).unwrap();
+        assert_eq!(loaded.meshes[0].face_colors, scene.meshes[0].face_colors);
+
+        save_stl(&loaded, &second).unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), std::fs::read(&second).unwrap());
+        let words = attributes(&first);
+        assert_eq!(words[0], 0x1f);
+        assert_eq!(words[1], 0x8000);
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn uncolored_files_stay_uncolored() {
+        let path = temp_path("uncolored.stl");
+        save_stl(&Scene { header: Vec::new(), meshes: vec![cube("cube", [0.0; 3], [1.0; 3])] }, &path).unwrap();
+        assert_eq!(load_stl(&path).unwrap().meshes[0].face_colors, None);
+        assert!(attributes(&path).iter().all(|&word| word == 0));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keeps_one_normal_per_facet() {
+        let path = temp_path("normals.stl");
+        let mesh = cube("cube", [0.0; 3], [1.0; 3]);
+        save_stl(&Scene { header: Vec::new(), meshes: vec![mesh.clone()] }, &path).unwrap();
+        let loaded = load_stl(&path).unwrap();
+        let expected: Vec<[f32; 3]> = (0..mesh.num_triangles()).map(|face| mesh.facet_normal(face)).collect();
+        assert_eq!(loaded.meshes[0].face_normals, Some(expected));
+        assert_eq!(loaded.meshes[0].normals, None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    const ASCII_TETRAHEDRON: &str = "solid tetra
+  facet normal 0 0 -1
+    outer loop
+      vertex 0 0 0
+      vertex 0 1 0
+      vertex 1 0 0
+    endloop
+  endfacet
+  facet normal 0 -1 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 0 1
+    endloop
+  endfacet
+  facet normal -1 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 0 0 1
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 1 1 1
+    outer loop
+      vertex 1 0 0
+      vertex 0 1 0
+      vertex 0 0 1
+    endloop
+  endfacet
+endsolid tetra
+";
+
+    #[test]
+    fn loads_ascii() {
+        let path = temp_path("ascii_load.stl");
+        std::fs::write(&path, ASCII_TETRAHEDRON).unwrap();
+        let scene = load_stl(&path).unwrap();
+        assert_eq!(scene.header, b"tetra");
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.num_triangles(), 4);
+        assert_eq!(mesh.triangle(3), [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(mesh.face_normals.as_ref().unwrap()[3], [1.0, 1.0, 1.0]);
+        assert!((mesh.signed_volume() - 1.0 / 6.0).abs() < 1e-9);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ascii_saves_as_binary_and_loads_back() {
+        let (ascii, binary) = (temp_path("ascii_source.stl"), temp_path("ascii_saved.stl"));
+        std::fs::write(&ascii, ASCII_TETRAHEDRON).unwrap();
+        let scene = load_stl(&ascii).unwrap();
+        save_stl(&scene, &binary).unwrap();
+        assert_eq!(std::fs::metadata(&binary).unwrap().len(), STL_HEADER_SIZE + 4 + 4 * STL_TRIANGLE_SIZE);
+        let reloaded = load_stl(&binary).unwrap();
+        assert_eq!(&reloaded.header[..5], b"tetra");
+        assert_eq!(reloaded.meshes[0].positions, scene.meshes[0].positions);
+        assert_eq!(reloaded.meshes[0].triangles, scene.meshes[0].triangles);
+        std::fs::remove_file(ascii).unwrap();
+        std::fs::remove_file(binary).unwrap();
+    }
+
+    #[test]
+    fn fans_out_ascii_polygons() {
+        let path = temp_path("ascii_quad.stl");
+        std::fs::write(&path, "solid quad\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 1 1 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid\n").unwrap();
+        let scene = load_stl(&path).unwrap();
+        assert_eq!(scene.meshes[0].num_triangles(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_ascii() {
+        let path = temp_path("ascii_malformed.stl");
+        std::fs::write(&path, ASCII_TETRAHEDRON.replace("vertex 0 1 0\n      vertex 1 0 0", "vertex 0 one 0\n      vertex 1 0 0")).unwrap();
+        assert!(matches!(load_stl(&path), Err(StlError::InvalidAscii { line: 5 })));
+
+        std::fs::write(&path, "solid short\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\nendfacet\n").unwrap();
+        assert!(matches!(load_stl(&path), Err(StlError::InvalidAscii { line: 6 })));
+
+        std::fs::write(&path, ASCII_TETRAHEDRON.replace("vertex 0 0 1\n    endloop\n  endfacet\nendsolid", "vertex 0 0 inf\n    endloop\n  endfacet\nendsolid")).unwrap();
+        assert!(matches!(load_stl(&path), Err(StlError::InvalidCoordinate { triangle: 3 })));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_binary() {
+        let path = temp_path("binary_truncated.stl");
+        let mut data = vec![0u8; STL_HEADER_SIZE as usize];
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0u8; STL_TRIANGLE_SIZE as usize]);
+        std::fs::write(&path, &data).unwrap();
+        assert!(matches!(load_stl(&path), Err(StlError::TriangleCountMismatch { num_tris: 2, .. })));
+        std::fs::write(&path, &data[..40]).unwrap();
+        assert!(matches!(load_stl(&path), Err(StlError::TruncatedHeader { file_len: 40 })));
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn refuses_more_facets_than_the_count_holds() {
+        assert_eq!(facet_count(12).unwrap(), 12);
+        assert_eq!(facet_count(u32::MAX as usize).unwrap(), u32::MAX);
+        let error = facet_count(u32::MAX as usize + 1).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
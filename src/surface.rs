@@ -1,11 +1,20 @@
 use wgpu::util::DeviceExt;
-use winit::{window::Window, event::WindowEvent};
+use winit::{window::Window, event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent}};
 // use crate::vertex::Vertex;
 use crate::camera::{Camera, CameraUniform};
-use crate::model::{self, ModelVertex, load_stl};
+use crate::mesh::Scene;
+use crate::model::{self, ModelVertex};
 use crate::model::Vertex;
+use crate::format::save;
+use std::path::PathBuf;
+
+use crate::cavity::{fill_scene, find_cavities, FillMode};
+use crate::shell::extract_shells;
+use crate::weld::weld;
 use crate::texture::{self, Texture};
 
+const WELD_EPSILON: f32 = 0.0;
+
 pub struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -19,13 +28,14 @@ pub struct State {
     clear_color: wgpu::Color,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     camera: Camera,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    scene: Scene,
+    save_path: PathBuf,
     stl_model: model::Model,
     depth_texture: Texture,
 }
@@ -42,7 +52,7 @@ const INDICES: &[u16] = &[
 ];
 
 impl State {
-    pub async fn new(window: Window) -> Self {
+    pub async fn new(window: Window, mut scene: Scene, save_path: PathBuf) -> Self {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -210,8 +220,6 @@ impl State {
             }
         );
 
-        let num_vertices = VERTICES.len() as u32;
-        
         let index_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
@@ -223,8 +231,28 @@ impl State {
         let num_indices = INDICES.len() as u32;
 
         // Load the model
-        let stl_model = load_stl(
-            "D:\\stls\\VrondissMini.stl", &device, &queue).await;
+        for mesh in scene.meshes.iter_mut() {
+            let welded = weld(mesh, WELD_EPSILON);
+            log::info!("Welded {} from {} down to {} vertices", mesh.name, mesh.positions.len(), welded.positions.len());
+            *mesh = welded;
+        }
+
+        // Show every connected shell as its own mesh
+        let shells: Vec<_> = scene.meshes.iter()
+            .flat_map(extract_shells)
+            .collect();
+        for shell in &shells {
+            log::info!(
+                "{}: {} triangles, volume {:.3}, surface area {:.3}",
+                shell.mesh.name, shell.stats.num_triangles, shell.stats.signed_volume, shell.stats.surface_area
+            );
+        }
+        log::info!("{}", find_cavities(&shells));
+        let shell_scene = Scene {
+            header: scene.header.clone(),
+            meshes: shells.into_iter().map(|shell| shell.mesh).collect(),
+        };
+        let stl_model = model::Model::upload(&shell_scene, &device);
 
         Self {
             window,
@@ -236,13 +264,14 @@ impl State {
             clear_color,
             render_pipeline,
             vertex_buffer,
-            num_vertices,
             index_buffer,
             num_indices,
             camera,
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            scene,
+            save_path,
             stl_model,
             depth_texture
         }
@@ -273,11 +302,33 @@ impl State {
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        // false
-
         match event {
-            
-            _ => {false}
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::S),
+                        ..
+                    },
+                ..
+            } => {
+                let (solid, summaries) = match fill_scene(&self.scene, FillMode::All, WELD_EPSILON) {
+                    Ok(filled) => filled,
+                    Err(e) => {
+                        log::error!("Failed to fill cavities: {}", e);
+                        return true;
+                    },
+                };
+                for (mesh, summary) in solid.meshes.iter().zip(&summaries) {
+                    log::info!("{}: {}", mesh.name, summary);
+                }
+                match save(&solid, &self.save_path) {
+                    Ok(_) => log::info!("Saved {}", self.save_path.display()),
+                    Err(e) => log::error!("Failed to save {}: {}", self.save_path.display(), e),
+                }
+                true
+            },
+            _ => false
         }
     }
 
@@ -331,7 +382,9 @@ impl State {
             render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
         
             use model::DrawModel;
-            render_pass.draw_mesh(&self.stl_model.meshes[0]);
+            for mesh in &self.stl_model.meshes {
+                render_pass.draw_mesh(mesh);
+            }
 
         }
 
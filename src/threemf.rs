@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::Path;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::mesh::{Scene, TriMesh};
+
+const DEFAULT_MODEL_PATH: &str = "3D/3dmodel.model";
+const MODEL_RELATIONSHIP: &str = "http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel";
+const CORE_NAMESPACE: &str = "http://schemas.microsoft.com/3dmanufacturing/core/2015/02";
+// Components referencing components, deeper than this is almost certainly a cycle
+const MAX_COMPONENT_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub enum ThreeMfError {
+    NotFound(String),
+    MissingModel(String),
+    InvalidAttribute { element: String, attribute: String },
+    MissingObject(u32),
+    IndexOutOfRange { object: u32, index: u32 },
+    InvalidCoordinate { object: u32, vertex: usize },
+    ComponentCycle(u32),
+    Zip(zip::result::ZipError),
+    Xml(roxmltree::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ThreeMfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThreeMfError::NotFound(path) => write!(f, "{} does not exist", path),
+            ThreeMfError::MissingModel(path) => write!(f, "package has no model part at {}", path),
+            ThreeMfError::InvalidAttribute { element, attribute } => write!(
+                f, "<{}> has a missing or malformed {} attribute", element, attribute),
+            ThreeMfError::MissingObject(id) => write!(f, "reference to undefined object {}", id),
+            ThreeMfError::IndexOutOfRange { object, index } => write!(
+                f, "object {} has a triangle referencing missing vertex {}", object, index),
+            ThreeMfError::InvalidCoordinate { object, vertex } => write!(
+                f, "object {} vertex {} has a NaN or infinite coordinate", object, vertex),
+            ThreeMfError::ComponentCycle(id) => write!(f, "components of object {} nest too deeply", id),
+            ThreeMfError::Zip(e) => write!(f, "{}", e),
+            ThreeMfError::Xml(e) => write!(f, "{}", e),
+            ThreeMfError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ThreeMfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ThreeMfError::Zip(e) => Some(e),
+            ThreeMfError::Xml(e) => Some(e),
+            ThreeMfError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ThreeMfError {
+    fn from(e: std::io::Error) -> Self {
+        ThreeMfError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ThreeMfError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ThreeMfError::Zip(e)
+    }
+}
+
+impl From<roxmltree::Error> for ThreeMfError {
+    fn from(e: roxmltree::Error) -> Self {
+        ThreeMfError::Xml(e)
+    }
+}
+
+// Row-major 4x3 affine matrix as written in the transform attribute,
+// points are row vectors multiplied on the left
+type Transform = [f64; 12];
+
+const IDENTITY: Transform = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+
+fn transform_point(t: &Transform, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    let (x, y, z) = (x as f64, y as f64, z as f64);
+    [0, 1, 2].map(|c| (x * t[c] + y * t[3 + c] + z * t[6 + c] + t[9 + c]) as f32)
+}
+
+// The transform that applies first and then second
+fn compose(first: &Transform, second: &Transform) -> Transform {
+    let mut result = [0.0; 12];
+    for row in 0..4 {
+        for c in 0..3 {
+            result[row * 3 + c] = (0..3).map(|k| first[row * 3 + k] * second[k * 3 + c]).sum::<f64>()
+                + if row == 3 { second[9 + c] } else { 0.0 };
+        }
+    }
+    result
+}
+
+struct Object {
+    name: Option<String>,
+    positions: Vec<[f32; 3]>,
+    triangles: Vec<[u32; 3]>,
+    components: Vec<(u32, Transform)>,
+}
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, ThreeMfError> {
+    node.attribute(name)
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| ThreeMfError::InvalidAttribute {
+            element: node.tag_name().name().to_string(),
+            attribute: name.to_string(),
+        })
+}
+
+fn parse_transform(node: roxmltree::Node) -> Result<Transform, ThreeMfError> {
+    let Some(value) = node.attribute("transform") else {
+        return Ok(IDENTITY);
+    };
+    let values: Vec<f64> = value.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+    values.try_into().map_err(|_| ThreeMfError::InvalidAttribute {
+        element: node.tag_name().name().to_string(),
+        attribute: "transform".to_string(),
+    })
+}
+
+// Scale from the model's unit to millimetres, which everything else assumes
+fn unit_scale(unit: Option<&str>) -> f64 {
+    match unit {
+        Some("micron") => 0.001,
+        Some("centimeter") => 10.0,
+        Some("inch") => 25.4,
+        Some("foot") => 304.8,
+        Some("meter") => 1000.0,
+        _ => 1.0,
+    }
+}
+
+fn children<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> + 'a {
+    node.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn parse_object(node: roxmltree::Node, id: u32) -> Result<Object, ThreeMfError> {
+    let mut object = Object {
+        name: node.attribute("name").map(str::to_string),
+        positions: Vec::new(),
+        triangles: Vec::new(),
+        components: Vec::new(),
+    };
+
+    for mesh in children(node, "mesh") {
+        for vertices in children(mesh, "vertices") {
+            for vertex in children(vertices, "vertex") {
+                let position: [f32; 3] = [attribute(vertex, "x")?, attribute(vertex, "y")?, attribute(vertex, "z")?];
+                if position.iter().any(|v| !v.is_finite()) {
+                    return Err(ThreeMfError::InvalidCoordinate { object: id, vertex: object.positions.len() });
+                }
+                object.positions.push(position);
+            }
+        }
+        for triangles in children(mesh, "triangles") {
+            for triangle in children(triangles, "triangle") {
+                object.triangles.push([attribute(triangle, "v1")?, attribute(triangle, "v2")?, attribute(triangle, "v3")?]);
+            }
+        }
+    }
+    for components in children(node, "components") {
+        for component in children(components, "component") {
+            object.components.push((attribute(component, "objectid")?, parse_transform(component)?));
+        }
+    }
+
+    Ok(object)
+}
+
+// Flattens an object and everything it references into one mesh
+fn append_object(
+    objects: &HashMap<u32, Object>,
+    id: u32,
+    transform: &Transform,
+    mesh: &mut TriMesh,
+    depth: usize,
+) -> Result<(), ThreeMfError> {
+    if depth > MAX_COMPONENT_DEPTH {
+        return Err(ThreeMfError::ComponentCycle(id));
+    }
+    let object = objects.get(&id).ok_or(ThreeMfError::MissingObject(id))?;
+
+    let offset = mesh.positions.len() as u32;
+    mesh.positions.extend(object.positions.iter().map(|&position| transform_point(transform, position)));
+    // A transform or unit scale can still push finite vertices out of range
+    if let Some(vertex) = mesh.positions[offset as usize..].iter().position(|position| position.iter().any(|v| !v.is_finite())) {
+        return Err(ThreeMfError::InvalidCoordinate { object: id, vertex });
+    }
+    for tri in &object.triangles {
+        if let Some(&index) = tri.iter().find(|&&index| index as usize >= object.positions.len()) {
+            return Err(ThreeMfError::IndexOutOfRange { object: id, index });
+        }
+        mesh.triangles.push(tri.map(|index| index + offset));
+    }
+
+    for (component, component_transform) in &object.components {
+        append_object(objects, *component, &compose(component_transform, transform), mesh, depth + 1)?;
+    }
+    Ok(())
+}
+
+// The model part is normally 3D/3dmodel.model, but the package relationships
+// are what actually say where it lives
+fn model_path(archive: &mut ZipArchive<File>) -> String {
+    let mut rels = String::new();
+    let found = archive.by_name("_rels/.rels")
+        .ok()
+        .and_then(|mut part| part.read_to_string(&mut rels).ok());
+    found.and_then(|_| {
+        let document = roxmltree::Document::parse(&rels).ok()?;
+        let target = document.descendants()
+            .filter(|node| node.tag_name().name() == "Relationship")
+            .find(|node| node.attribute("Type") == Some(MODEL_RELATIONSHIP))?
+            .attribute("Target")?;
+        Some(target.trim_start_matches('/').to_string())
+    })
+    .unwrap_or_else(|| DEFAULT_MODEL_PATH.to_string())
+}
+
+// 3MF packages are zip archives, which is as far as a sniff can tell
+pub fn is_3mf(sample: &[u8]) -> bool {
+    sample.starts_with(b"PK\x03\x04")
+}
+
+// One mesh per build item with its transform applied, or one per object
+// when the build section is empty. Geometry is converted to millimetres.
+pub fn load_3mf(file_path: impl AsRef<Path>) -> Result<Scene, ThreeMfError> {
+    let file_path = file_path.as_ref();
+    let file = File::open(file_path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ThreeMfError::NotFound(file_path.display().to_string()),
+        _ => ThreeMfError::Io(e),
+    })?;
+    let file_name = file_path
+        .file_stem()
+        .map_or_else(|| file_path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+
+    let mut archive = ZipArchive::new(file)?;
+    let model_path = model_path(&mut archive);
+    let mut xml = String::new();
+    archive.by_name(&model_path)
+        .map_err(|_| ThreeMfError::MissingModel(model_path.clone()))?
+        .read_to_string(&mut xml)?;
+
+    let document = roxmltree::Document::parse(&xml)?;
+    let model = document.root_element();
+    let scale = unit_scale(model.attribute("unit"));
+    let unit_transform: Transform = [scale, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0];
+
+    let mut objects = HashMap::new();
+    let mut object_order = Vec::new();
+    for resources in children(model, "resources") {
+        for node in children(resources, "object") {
+            let id: u32 = attribute(node, "id")?;
+            objects.insert(id, parse_object(node, id)?);
+            object_order.push(id);
+        }
+    }
+
+    let mut items: Vec<(u32, Transform)> = Vec::new();
+    for build in children(model, "build") {
+        for item in children(build, "item") {
+            items.push((attribute(item, "objectid")?, parse_transform(item)?));
+        }
+    }
+    if items.is_empty() {
+        items = object_order.iter().map(|&id| (id, IDENTITY)).collect();
+    }
+
+    let mut meshes = Vec::with_capacity(items.len());
+    for (id, transform) in &items {
+        let name = objects.get(id)
+            .and_then(|object| object.name.clone())
+            .unwrap_or_else(|| format!("{} object {}", file_name, id));
+        let mut mesh = TriMesh::new(&name);
+        append_object(&objects, *id, &compose(transform, &unit_transform), &mut mesh, 0)?;
+        meshes.push(mesh);
+    }
+
+    let header = children(model, "metadata")
+        .find(|node| node.attribute("name") == Some("Title"))
+        .and_then(|node| node.text())
+        .map(|text| text.as_bytes().to_vec())
+        .unwrap_or_default();
+
+    Ok(Scene { header, meshes })
+}
+
+// Control characters other than tab and line breaks can't appear in XML 1.0
+// at all, not even escaped, so they're dropped
+fn escape_xml(text: &str) -> String {
+    text.replace(|c: char| c < ' ' && !matches!(c, '\t' | '\n' | '\r'), "")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+ <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+ <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+// One object and one build item per mesh, in millimetres
+pub fn save_3mf(scene: &Scene, file_path: impl AsRef<Path>) -> Result<(), ThreeMfError> {
+    use std::fmt::Write as _;
+    use std::io::Write;
+
+    let mut model = String::new();
+    writeln!(model, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(model, r#"<model unit="millimeter" xml:lang="en-US" xmlns="{}">"#, CORE_NAMESPACE).unwrap();
+    let title = String::from_utf8_lossy(&scene.header);
+    let title = title.trim_end_matches('\0').trim();
+    if !title.is_empty() {
+        writeln!(model, r#" <metadata name="Title">{}</metadata>"#, escape_xml(title)).unwrap();
+    }
+
+    writeln!(model, " <resources>").unwrap();
+    for (index, mesh) in scene.meshes.iter().enumerate() {
+        writeln!(model, r#"  <object id="{}" name="{}" type="model">"#, index + 1, escape_xml(&mesh.name)).unwrap();
+        writeln!(model, "   <mesh>").unwrap();
+        writeln!(model, "    <vertices>").unwrap();
+        for [x, y, z] in &mesh.positions {
+            writeln!(model, r#"     <vertex x="{}" y="{}" z="{}"/>"#, x, y, z).unwrap();
+        }
+        writeln!(model, "    </vertices>").unwrap();
+        writeln!(model, "    <triangles>").unwrap();
+        for [v1, v2, v3] in &mesh.triangles {
+            writeln!(model, r#"     <triangle v1="{}" v2="{}" v3="{}"/>"#, v1, v2, v3).unwrap();
+        }
+        writeln!(model, "    </triangles>").unwrap();
+        writeln!(model, "   </mesh>").unwrap();
+        writeln!(model, "  </object>").unwrap();
+    }
+    writeln!(model, " </resources>").unwrap();
+
+    writeln!(model, " <build>").unwrap();
+    for index in 0..scene.meshes.len() {
+        writeln!(model, r#"  <item objectid="{}"/>"#, index + 1).unwrap();
+    }
+    writeln!(model, " </build>").unwrap();
+    writeln!(model, "</model>").unwrap();
+
+    let rels = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#, "\n",
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#, "\n",
+            r#" <Relationship Target="/{}" Id="rel0" Type="{}"/>"#, "\n",
+            "</Relationships>\n"
+        ),
+        DEFAULT_MODEL_PATH, MODEL_RELATIONSHIP
+    );
+
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(file_path)?));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("[Content_Types].xml", options)?;
+    zip.write_all(CONTENT_TYPES.as_bytes())?;
+    zip.start_file("_rels/.rels", options)?;
+    zip.write_all(rels.as_bytes())?;
+    zip.start_file(DEFAULT_MODEL_PATH, options)?;
+    zip.write_all(model.as_bytes())?;
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{cube, temp_path};
+
+    // A package holding just the model part at the default path
+    fn write_package(name: &str, model: &str) -> std::path::PathBuf {
+        use std::io::Write;
+
+        let path = temp_path(name);
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file(DEFAULT_MODEL_PATH, FileOptions::default()).unwrap();
+        zip.write_all(model.as_bytes()).unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    fn single_triangle(unit: &str, vertices: [&str; 3], transform: &str) -> String {
+        format!(
+            r#"<model unit="{}" xmlns="{}"><resources><object id="1" type="model"><mesh>
+<vertices><vertex {}/><vertex {}/><vertex {}/></vertices>
+<triangles><triangle v1="0" v2="1" v3="2"/></triangles>
+</mesh></object></resources><build><item objectid="1" transform="{}"/></build></model>"#,
+            unit, CORE_NAMESPACE, vertices[0], vertices[1], vertices[2], transform
+        )
+    }
+
+    #[test]
+    fn drops_characters_xml_cannot_hold() {
+        let path = temp_path("control.3mf");
+        let scene = Scene {
+            header: b"title\x01 with <markup> & \"quotes\"\x1b\0\0".to_vec(),
+            meshes: vec![cube("part\x07 one", [0.0; 3], [1.0; 3])],
+        };
+        save_3mf(&scene, &path).unwrap();
+        let loaded = load_3mf(&path).unwrap();
+        assert_eq!(loaded.header, b"title with <markup> & \"quotes\"");
+        assert_eq!(loaded.meshes[0].name, "part one");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_non_finite_coordinates() {
+        let identity = "1 0 0 0 1 0 0 0 1 0 0 0";
+        let vertices = [r#"x="0" y="0" z="0""#, r#"x="1" y="0" z="0""#, r#"x="0" y="1" z="0""#];
+
+        let path = write_package("nan.3mf", &single_triangle("millimeter", [vertices[0], r#"x="nan" y="0" z="0""#, vertices[2]], identity));
+        assert!(matches!(load_3mf(&path), Err(ThreeMfError::InvalidCoordinate { object: 1, vertex: 1 })));
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_package("inf.3mf", &single_triangle("millimeter", [vertices[0], vertices[1], r#"x="0" y="inf" z="0""#], identity));
+        assert!(matches!(load_3mf(&path), Err(ThreeMfError::InvalidCoordinate { object: 1, vertex: 2 })));
+        std::fs::remove_file(path).unwrap();
+
+        // Finite in the file, but not once scaled to millimetres
+        let path = write_package("overflow.3mf", &single_triangle("meter", vertices, "1e36 0 0 0 1 0 0 0 1 0 0 0"));
+        assert!(matches!(load_3mf(&path), Err(ThreeMfError::InvalidCoordinate { object: 1, vertex: 1 })));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn loads_components_with_units_and_transforms() {
+        let model = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="centimeter" xmlns="{}">
+ <metadata name="Title">Bracket</metadata>
+ <resources>
+  <object id="1" type="model"><mesh>
+   <vertices><vertex x="0" y="0" z="0"/><vertex x="1" y="0" z="0"/><vertex x="0" y="1" z="0"/></vertices>
+   <triangles><triangle v1="0" v2="1" v3="2"/></triangles>
+  </mesh></object>
+  <object id="2" name="pair" type="model"><components>
+   <component objectid="1"/>
+   <component objectid="1" transform="1 0 0 0 1 0 0 0 1 0 0 2"/>
+  </components></object>
+ </resources>
+ <build><item objectid="2" transform="1 0 0 0 1 0 0 0 1 5 0 0"/></build>
+</model>"#,
+            CORE_NAMESPACE
+        );
+        let path = write_package("components.3mf", &model);
+        let scene = load_3mf(&path).unwrap();
+        assert_eq!(scene.header, b"Bracket");
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.name, "pair");
+        assert_eq!(mesh.num_triangles(), 2);
+        assert_eq!(mesh.triangle(0), [[50.0, 0.0, 0.0], [60.0, 0.0, 0.0], [50.0, 10.0, 0.0]]);
+        assert_eq!(mesh.triangle(1), [[50.0, 0.0, 20.0], [60.0, 0.0, 20.0], [50.0, 10.0, 20.0]]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saves_and_loads_back() {
+        let path = temp_path("round_trip.3mf");
+        let scene = Scene {
+            header: b"Two boxes".to_vec(),
+            meshes: vec![cube("small", [0.0; 3], [1.0; 3]), cube("large", [-2.5; 3], [0.125, 3.0, 7.75])],
+        };
+        save_3mf(&scene, &path).unwrap();
+        assert!(is_3mf(&std::fs::read(&path).unwrap()));
+
+        let loaded = load_3mf(&path).unwrap();
+        assert_eq!(loaded.header, scene.header);
+        assert_eq!(loaded.meshes.len(), 2);
+        for (loaded, saved) in loaded.meshes.iter().zip(&scene.meshes) {
+            assert_eq!(loaded.name, saved.name);
+            assert_eq!(loaded.positions, saved.positions);
+            assert_eq!(loaded.triangles, saved.triangles);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn load_error(name: &str, model: &str) -> ThreeMfError {
+        let path = write_package(name, model);
+        let error = load_3mf(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        error
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let vertices = [r#"x="0" y="0" z="0""#, r#"x="1" y="0" z="0""#, r#"x="0" y="1" z="0""#];
+        let valid = single_triangle("millimeter", vertices, "1 0 0 0 1 0 0 0 1 0 0 0");
+
+        let error = load_error("out_of_range.3mf", &valid.replace(r#"v3="2""#, r#"v3="3""#));
+        assert!(matches!(error, ThreeMfError::IndexOutOfRange { object: 1, index: 3 }), "{:?}", error);
+        let error = load_error("missing_object.3mf", &valid.replace(r#"item objectid="1""#, r#"item objectid="7""#));
+        assert!(matches!(error, ThreeMfError::MissingObject(7)), "{:?}", error);
+        let error = load_error("bad_attribute.3mf", &valid.replace(r#"x="1""#, r#"x="one""#));
+        assert!(matches!(&error, ThreeMfError::InvalidAttribute { element, attribute } if element == "vertex" && attribute == "x"), "{:?}", error);
+        let error = load_error("bad_transform.3mf", &valid.replace("0 0 1 0 0 0", "0 0 1 0 0"));
+        assert!(matches!(&error, ThreeMfError::InvalidAttribute { attribute, .. } if attribute == "transform"), "{:?}", error);
+        let error = load_error("not_xml.3mf", "<model><resources></model>");
+        assert!(matches!(error, ThreeMfError::Xml(_)), "{:?}", error);
+
+        let cycle = format!(
+            r#"<model xmlns="{}"><resources><object id="1"><components><component objectid="1"/></components></object></resources></model>"#,
+            CORE_NAMESPACE
+        );
+        let error = load_error("cycle.3mf", &cycle);
+        assert!(matches!(error, ThreeMfError::ComponentCycle(1)), "{:?}", error);
+
+        let path = temp_path("no_model.3mf");
+        ZipWriter::new(File::create(&path).unwrap()).finish().unwrap();
+        assert!(matches!(load_3mf(&path), Err(ThreeMfError::MissingModel(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+}
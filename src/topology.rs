@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use crate::mesh::TriMesh;
+
+pub struct Edge {
+    // Sorted so the same edge from either side maps to one entry
+    pub vertices: [u32; 2],
+    pub faces: Vec<u32>,
+}
+
+impl Edge {
+    pub fn is_boundary(&self) -> bool {
+        self.faces.len() == 1
+    }
+
+    pub fn is_non_manifold(&self) -> bool {
+        self.faces.len() > 2
+    }
+}
+
+// Edge to face adjacency for a welded mesh. Unlike a half-edge structure
+// this copes with non-manifold edges, which sculpt exports are full of.
+pub struct Topology {
+    pub triangles: Vec<[u32; 3]>,
+    pub edges: Vec<Edge>,
+    // Edge index for tri[i] -> tri[i + 1] of every face
+    pub face_edges: Vec<[u32; 3]>,
+    pub vertex_faces: Vec<Vec<u32>>,
+}
+
+impl Topology {
+    pub fn new(mesh: &TriMesh) -> Self {
+        let mut edge_lookup: HashMap<[u32; 2], u32> = HashMap::with_capacity(mesh.triangles.len() * 3 / 2);
+        let mut edges: Vec<Edge> = Vec::new();
+        let mut face_edges = Vec::with_capacity(mesh.triangles.len());
+        let mut vertex_faces = vec![Vec::new(); mesh.positions.len()];
+
+        for (face, tri) in mesh.triangles.iter().enumerate() {
+            let mut tri_edges = [0; 3];
+            for i in 0..3 {
+                let (a, b) = (tri[i], tri[(i + 1) % 3]);
+                let key = [a.min(b), a.max(b)];
+                let edge = *edge_lookup.entry(key).or_insert_with(|| {
+                    edges.push(Edge { vertices: key, faces: Vec::with_capacity(2) });
+                    edges.len() as u32 - 1
+                });
+                edges[edge as usize].faces.push(face as u32);
+                tri_edges[i] = edge;
+                vertex_faces[a as usize].push(face as u32);
+            }
+            face_edges.push(tri_edges);
+        }
+
+        Self {
+            triangles: mesh.triangles.clone(),
+            edges,
+            face_edges,
+            vertex_faces,
+        }
+    }
+
+    // Faces sharing an edge with the given face
+    pub fn face_neighbours(&self, face: u32) -> Vec<u32> {
+        let mut neighbours: Vec<u32> = self.face_edges[face as usize].iter()
+            .flat_map(|&edge| self.edges[edge as usize].faces.iter().copied())
+            .filter(|&other| other != face)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    // Vertices connected to the given vertex by an edge
+    pub fn vertex_ring(&self, vertex: u32) -> Vec<u32> {
+        let mut ring: Vec<u32> = self.vertex_faces[vertex as usize].iter()
+            .flat_map(|&face| self.triangles[face as usize])
+            .filter(|&other| other != vertex)
+            .collect();
+        ring.sort_unstable();
+        ring.dedup();
+        ring
+    }
+
+    // Oriented the way the single face using them winds
+    pub fn boundary_edges(&self) -> Vec<[u32; 2]> {
+        self.edges.iter()
+            .filter(|edge| edge.is_boundary())
+            .map(|edge| self.directed_edge(edge.faces[0], edge.vertices))
+            .collect()
+    }
+
+    pub fn non_manifold_edges(&self) -> Vec<[u32; 2]> {
+        self.edges.iter()
+            .filter(|edge| edge.is_non_manifold())
+            .map(|edge| edge.vertices)
+            .collect()
+    }
+
+    // The edge as the face walks it, either [a, b] or [b, a]
+    pub fn directed_edge(&self, face: u32, vertices: [u32; 2]) -> [u32; 2] {
+        let tri = self.triangles[face as usize];
+        for i in 0..3 {
+            let (a, b) = (tri[i], tri[(i + 1) % 3]);
+            if [a.min(b), a.max(b)] == vertices {
+                return [a, b];
+            }
+        }
+        vertices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    #[test]
+    fn closed_cube_is_manifold() {
+        let topology = Topology::new(&cube("cube", [0.0; 3], [1.0; 3]));
+        assert_eq!(topology.edges.len(), 18);
+        assert!(topology.edges.iter().all(|edge| edge.faces.len() == 2));
+        assert!(topology.boundary_edges().is_empty());
+        assert!(topology.non_manifold_edges().is_empty());
+        assert!(topology.vertex_faces.iter().all(|faces| !faces.is_empty()));
+    }
+
+    #[test]
+    fn finds_boundary_edges_the_way_faces_wind() {
+        let mut mesh = cube("open", [0.0; 3], [1.0; 3]);
+        let removed = mesh.triangles.pop().unwrap();
+        let topology = Topology::new(&mesh);
+
+        let mut boundary = topology.boundary_edges();
+        boundary.sort_unstable();
+        // The missing face walked each of its edges the other way
+        let mut expected: Vec<[u32; 2]> = (0..3).map(|i| [removed[(i + 1) % 3], removed[i]]).collect();
+        expected.sort_unstable();
+        assert_eq!(boundary, expected);
+        assert!(topology.non_manifold_edges().is_empty());
+    }
+
+    #[test]
+    fn finds_non_manifold_edges() {
+        // Three fins sharing the edge from vertex 0 to 1
+        let mut mesh = TriMesh::new("fins");
+        mesh.positions = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-1.0, -1.0, 0.0]];
+        mesh.triangles = vec![[0, 1, 2], [1, 0, 3], [0, 1, 4]];
+        let topology = Topology::new(&mesh);
+
+        assert_eq!(topology.non_manifold_edges(), vec![[0, 1]]);
+        let shared = topology.edges.iter().find(|edge| edge.vertices == [0, 1]).unwrap();
+        assert!(shared.is_non_manifold() && !shared.is_boundary());
+        assert_eq!(shared.faces, vec![0, 1, 2]);
+        assert_eq!(topology.boundary_edges().len(), 6);
+        assert_eq!(topology.face_neighbours(0), vec![1, 2]);
+        assert_eq!(topology.vertex_ring(0), vec![1, 2, 3, 4]);
+        assert_eq!(topology.directed_edge(1, [0, 1]), [1, 0]);
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use num_rational::BigRational;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::arrangement::{arrange, Arrangement};
+use crate::cavity::solid_angle;
+use crate::exact::{add, orient2d, projection_axes, scale, ExactPoint};
+use crate::mesh::{facet_normal, triangle_area, Bounds, TriMesh};
+use crate::shell::shell_faces;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct UnionReport {
+    // Pairs of faces crossing or overlapping each other
+    pub intersecting_pairs: usize,
+    pub split_faces: usize,
+    // Faces of the split mesh found inside the solid and dropped
+    pub removed_triangles: usize,
+    pub removed_area: f64,
+}
+
+// What resolving the crossings of a mesh did, for the reports built on it
+#[derive(Clone, Debug, Default)]
+pub struct Resolution {
+    pub intersecting_pairs: usize,
+    pub split_faces: usize,
+    pub removed_triangles: usize,
+    pub removed_area: f64,
+    pub flipped_triangles: usize,
+}
+
+// Which operands the space just in front of a patch and just behind it lies
+// inside, in front being where its faces' normals point
+#[derive(Clone, Debug)]
+pub struct Sides {
+    pub front: Vec<bool>,
+    pub behind: Vec<bool>,
+}
+
+#[derive(Debug)]
+pub enum UnionError {
+    // Exact arithmetic has nothing to stand in for NaN or infinity
+    InvalidCoordinate { name: String, vertex: u32 },
+}
+
+// A connected part of the input, for only looking at nearby parts when
+// working out whether a point is inside the solid
+struct Part {
+    faces: Vec<u32>,
+    bounds: Bounds,
+}
+
+// Merges the overlapping closed parts of a welded, consistently oriented
+// mesh into the one surface around all of them. Faces are split exactly
+// where they cross, then each patch between the crossings is kept only if
+// it separates the outside from the solid. Parts buried inside others are
+// dropped whole. Cavity walls face into the cavity, so cavities are left
+// for cavity detection.
+pub fn self_union(mesh: &TriMesh) -> Result<(TriMesh, UnionReport), UnionError> {
+    let operands = vec![0; mesh.num_triangles()];
+    // Faces facing the wrong way are dropped rather than turned around
+    let (merged, resolution) = resolve(mesh, &operands, 1, |sides| (sides.behind[0] && !sides.front[0]).then_some(false))?;
+    let report = UnionReport {
+        intersecting_pairs: resolution.intersecting_pairs,
+        split_faces: resolution.split_faces,
+        removed_triangles: resolution.removed_triangles,
+        removed_area: resolution.removed_area,
+    };
+    Ok((merged, report))
+}
+
+// Splits the faces of a welded mesh exactly where they cross and decides
+// for every patch between the crossings whether it stays and which way it
+// faces. Each face belongs to one of the operands, all closed and wound
+// outwards. The decision gets the operands either side of the patch, and
+// says to keep it as it is, keep it turned around or drop it. Of coplanar
+// faces covering the same spot, only the first one kept stays. Every
+// vertex a face uses has to be finite, which is checked here once so the
+// exact predicates further in can rely on it.
+pub fn resolve<F>(mesh: &TriMesh, operands: &[usize], num_operands: usize, decide: F) -> Result<(TriMesh, Resolution), UnionError>
+where
+    F: Fn(&Sides) -> Option<bool> + Sync,
+{
+    let invalid = mesh.triangles.iter()
+        .flatten()
+        .copied()
+        .find(|&vertex| !mesh.positions[vertex as usize].iter().all(|v| v.is_finite()));
+    if let Some(vertex) = invalid {
+        return Err(UnionError::InvalidCoordinate { name: mesh.name.clone(), vertex });
+    }
+
+    let mut arrangement = arrange(mesh);
+    let patches = patches(&arrangement);
+
+    let parts: Vec<Part> = shell_faces(mesh).into_iter()
+        .map(|faces| {
+            let first = mesh.positions[mesh.triangles[faces[0] as usize][0] as usize];
+            let bounds = faces.iter()
+                .flat_map(|&face| mesh.triangles[face as usize])
+                .fold(Bounds { min: first, max: first }, |bounds, vertex| bounds.including(mesh.positions[vertex as usize]));
+            Part { faces, bounds }
+        })
+        .collect();
+    let mut partners: HashMap<u32, Vec<u32>> = HashMap::new();
+    for &[a, b] in &arrangement.coplanar {
+        partners.entry(a).or_default().push(b);
+        partners.entry(b).or_default().push(a);
+    }
+
+    let context = Context { mesh, arrangement: &arrangement, parts: &parts, partners: &partners, operands, num_operands };
+    let verdicts: Vec<Option<bool>> = patches.par_iter()
+        .map(|faces| patch_verdict(&context, faces, &decide))
+        .collect();
+    let mut keep = vec![false; arrangement.triangles.len()];
+    let mut resolution = Resolution {
+        intersecting_pairs: arrangement.intersecting_pairs,
+        split_faces: arrangement.split_faces,
+        ..Default::default()
+    };
+    for (faces, &verdict) in patches.iter().zip(&verdicts) {
+        for &face in faces {
+            match verdict {
+                Some(flip) => {
+                    keep[face as usize] = true;
+                    if flip {
+                        arrangement.triangles[face as usize].swap(1, 2);
+                        resolution.flipped_triangles += 1;
+                    }
+                },
+                None => {
+                    resolution.removed_triangles += 1;
+                    resolution.removed_area += triangle_area(approx_triangle(&arrangement, face));
+                },
+            }
+        }
+    }
+
+    Ok((arrangement.to_mesh(mesh, &keep), resolution))
+}
+
+fn approx_triangle(arrangement: &Arrangement, face: u32) -> [[f32; 3]; 3] {
+    arrangement.triangles[face as usize].map(|point| arrangement.points[point as usize].to_f32())
+}
+
+// Faces of the arrangement connected across edges that aren't seams, so
+// each patch lies wholly inside or outside everything else
+fn patches(arrangement: &Arrangement) -> Vec<Vec<u32>> {
+    let mut edge_faces: HashMap<[u32; 2], Vec<u32>> = HashMap::new();
+    for (face, tri) in arrangement.triangles.iter().enumerate() {
+        for i in 0..3 {
+            let (a, b) = (tri[i], tri[(i + 1) % 3]);
+            edge_faces.entry([a.min(b), a.max(b)]).or_default().push(face as u32);
+        }
+    }
+
+    let mut patch_of: Vec<Option<usize>> = vec![None; arrangement.triangles.len()];
+    let mut patches: Vec<Vec<u32>> = Vec::new();
+    for start in 0..arrangement.triangles.len() {
+        if patch_of[start].is_some() {
+            continue;
+        }
+
+        let patch = patches.len();
+        let mut faces = vec![start as u32];
+        patch_of[start] = Some(patch);
+        let mut next = 0;
+        while next < faces.len() {
+            let tri = arrangement.triangles[faces[next] as usize];
+            next += 1;
+            for i in 0..3 {
+                let (a, b) = (tri[i], tri[(i + 1) % 3]);
+                let edge = [a.min(b), a.max(b)];
+                // Seams and non-manifold edges are where patches meet
+                match edge_faces[&edge][..] {
+                    [first, second] if !arrangement.seams.contains(&edge) => {
+                        for neighbour in [first, second] {
+                            if patch_of[neighbour as usize].is_none() {
+                                patch_of[neighbour as usize] = Some(patch);
+                                faces.push(neighbour);
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        patches.push(faces);
+    }
+    patches
+}
+
+// Everything deciding a patch needs to know about the mesh
+struct Context<'a> {
+    mesh: &'a TriMesh,
+    arrangement: &'a Arrangement,
+    parts: &'a [Part],
+    partners: &'a HashMap<u32, Vec<u32>>,
+    operands: &'a [usize],
+    num_operands: usize,
+}
+
+// What happens to a patch, going by its largest face. The winding number
+// of each operand at its centre without its own face counts half of what
+// crossing the surface there adds, which sits between the winding number
+// in front of it and behind it. Coplanar faces covering the same spot add
+// their share and are left out the same way. A covering face facing the
+// other way sees the two sides swapped.
+fn patch_verdict<F>(context: &Context, faces: &[u32], decide: &F) -> Option<bool>
+where
+    F: Fn(&Sides) -> Option<bool>,
+{
+    let Context { mesh, arrangement, parts, partners, operands, num_operands } = *context;
+    let sample = *faces.iter()
+        .max_by(|&&a, &&b| triangle_area(approx_triangle(arrangement, a)).total_cmp(&triangle_area(approx_triangle(arrangement, b))))
+        .unwrap();
+    let corners = arrangement.triangles[sample as usize].map(|point| &arrangement.points[point as usize].exact);
+    let third = BigRational::new(1.into(), 3.into());
+    let centre = ExactPoint::new(scale(&add(&add(corners[0], corners[1]), corners[2]), &third));
+
+    let source = arrangement.source[sample as usize];
+    let normal = Vector3::from(mesh.facet_normal(source as usize));
+    let facing = |face: u32| Vector3::from(mesh.facet_normal(face as usize)).dot(normal) > 0.0;
+    let covering: Vec<u32> = partners.get(&source).into_iter()
+        .flatten()
+        .copied()
+        .filter(|&other| covers(mesh, other, &centre))
+        .collect();
+
+    let point = Vector3::from(centre.approx);
+    let mut winding = vec![0.0; num_operands];
+    let nearby = parts.iter()
+        .filter(|part| (0..3).all(|i| part.bounds.min[i] as f64 <= point[i] && point[i] <= part.bounds.max[i] as f64))
+        .flat_map(|part| &part.faces)
+        .filter(|&&face| face != source && !covering.contains(&face));
+    for &face in nearby {
+        winding[operands[face as usize]] += solid_angle(mesh.triangle(face as usize), point) / (4.0 * std::f64::consts::PI);
+    }
+    let mut crossing = vec![0.0; num_operands];
+    for &face in covering.iter().chain([&source]) {
+        crossing[operands[face as usize]] += if face == source || facing(face) { 1.0 } else { -1.0 };
+    }
+    let sides = Sides {
+        front: (0..num_operands).map(|i| winding[i] - crossing[i] / 2.0 > 0.5).collect(),
+        behind: (0..num_operands).map(|i| winding[i] + crossing[i] / 2.0 > 0.5).collect(),
+    };
+
+    let verdict = decide(&sides);
+    let swapped = Sides { front: sides.behind.clone(), behind: sides.front.clone() };
+    let kept_before = covering.iter()
+        .filter(|&&other| other < source)
+        .any(|&other| decide(if facing(other) { &sides } else { &swapped }).is_some());
+    if kept_before {
+        None
+    } else {
+        verdict
+    }
+}
+
+// Whether a coplanar face's interior covers the point
+fn covers(mesh: &TriMesh, face: u32, point: &ExactPoint) -> bool {
+    let corners = mesh.triangle(face as usize);
+    let axes = projection_axes(facet_normal(corners[0], corners[1], corners[2]).map(|v| v as f64));
+    let corners = corners.map(ExactPoint::from_f32);
+    (0..3).all(|i| orient2d(&corners[i], &corners[(i + 1) % 3], point, axes).is_gt())
+}
+
+impl fmt::Display for UnionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "split {} faces where {} pairs cross, removed {} internal triangles covering {:.3}",
+            self.split_faces, self.intersecting_pairs, self.removed_triangles, self.removed_area
+        )
+    }
+}
+
+impl fmt::Display for UnionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnionError::InvalidCoordinate { name, vertex } => {
+                write!(f, "{} vertex {} has a NaN or infinite coordinate", name, vertex)
+            },
+        }
+    }
+}
+
+impl std::error::Error for UnionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+    use crate::topology::Topology;
+    use crate::weld::weld;
+
+    #[test]
+    fn merges_overlapping_cubes() {
+        let mut mesh = cube("pair", [0.0; 3], [10.0; 3]);
+        mesh.append(&cube("pair", [5.0; 3], [15.0; 3]));
+        let (merged, report) = self_union(&weld(&mesh, 0.0)).unwrap();
+        assert!(report.intersecting_pairs > 0);
+        assert!(report.removed_triangles > 0);
+        assert!(Topology::new(&merged).boundary_edges().is_empty());
+        assert!((merged.signed_volume() - 1875.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn drops_buried_parts() {
+        let mut mesh = cube("nested", [0.0; 3], [10.0; 3]);
+        mesh.append(&cube("nested", [3.0; 3], [7.0; 3]));
+        let (merged, report) = self_union(&mesh).unwrap();
+        assert_eq!(report.removed_triangles, 12);
+        assert_eq!(merged.num_triangles(), 12);
+        assert!((merged.signed_volume() - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_non_finite_coordinates() {
+        for bad in [f32::NAN, f32::INFINITY] {
+            let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+            mesh.positions[5][1] = bad;
+            match self_union(&mesh) {
+                Err(UnionError::InvalidCoordinate { vertex: 5, .. }) => {},
+                other => panic!("expected an invalid coordinate, got {:?}", other.map(|(_, report)| report)),
+            }
+        }
+    }
+}
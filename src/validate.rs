@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use serde::Serialize;
+
+use crate::broadphase::overlapping_pairs;
+use crate::exact::{orient2d_f32, projection_axes, side_of_plane};
+use crate::mesh::{facet_normal, Scene, TriMesh};
+use crate::topology::Topology;
+use crate::weld::weld;
+
+// Barycentric and segment slack for the intersection test, so triangles
+// that merely touch along an edge or at a shared corner don't count
+const INTERSECTION_EPSILON: f64 = 1e-9;
+// How many of each kind of problem the text report lists
+const MAX_LISTED: usize = 5;
+
+// Indices refer to the mesh that was validated, so they can be used to
+// highlight its vertices and faces directly. Locations are in model space.
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BoundaryLoop {
+    // In the order the boundary is walked, following the winding of the faces
+    pub vertices: Vec<u32>,
+    pub length: f64,
+    // False when the walk ran into a non-manifold vertex before getting back
+    // to where it started
+    pub closed: bool,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EdgeIssue {
+    pub vertices: [u32; 2],
+    pub faces: Vec<u32>,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct VertexIssue {
+    pub vertex: u32,
+    // Separate fans of faces meeting at the vertex, two for a bowtie
+    pub fans: usize,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FaceIssue {
+    pub faces: Vec<u32>,
+    pub location: [f32; 3],
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub boundary_loops: Vec<BoundaryLoop>,
+    pub non_manifold_edges: Vec<EdgeIssue>,
+    pub non_manifold_vertices: Vec<VertexIssue>,
+    // Edges whose two faces both walk it in the same direction
+    pub inconsistent_edges: Vec<EdgeIssue>,
+    pub degenerate_triangles: Vec<FaceIssue>,
+    // Faces using the same three vertices, whichever way they wind
+    pub duplicate_faces: Vec<FaceIssue>,
+    pub self_intersections: Vec<FaceIssue>,
+    // Faces lying in the same plane and covering some of the same area
+    pub coplanar_overlaps: Vec<FaceIssue>,
+}
+
+#[derive(Serialize)]
+pub struct MeshValidation {
+    pub name: String,
+    #[serde(flatten)]
+    pub report: ValidationReport,
+}
+
+#[derive(Serialize)]
+pub struct FileValidation {
+    pub file: String,
+    pub meshes: Vec<MeshValidation>,
+}
+
+impl FileValidation {
+    pub fn num_problems(&self) -> usize {
+        self.meshes.iter().map(|mesh| mesh.report.num_problems()).sum()
+    }
+}
+
+impl ValidationReport {
+    // Closed, manifold and consistently oriented, what cavity detection assumes
+    pub fn is_watertight(&self) -> bool {
+        self.boundary_loops.is_empty()
+            && self.non_manifold_edges.is_empty()
+            && self.inconsistent_edges.is_empty()
+    }
+
+    pub fn num_problems(&self) -> usize {
+        self.boundary_loops.len()
+            + self.non_manifold_edges.len()
+            + self.non_manifold_vertices.len()
+            + self.inconsistent_edges.len()
+            + self.degenerate_triangles.len()
+            + self.duplicate_faces.len()
+            + self.self_intersections.len()
+            + self.coplanar_overlaps.len()
+    }
+}
+
+// Expects a welded mesh, otherwise every edge is a boundary
+pub fn validate(mesh: &TriMesh) -> ValidationReport {
+    let topology = Topology::new(mesh);
+    let edge_midpoint = |[a, b]: [u32; 2]| midpoint(&[mesh.positions[a as usize], mesh.positions[b as usize]]);
+    let face_centre = |face: u32| midpoint(&mesh.triangle(face as usize));
+
+    let edge_issue = |vertices: [u32; 2], faces: &[u32]| EdgeIssue {
+        vertices,
+        faces: faces.to_vec(),
+        location: edge_midpoint(vertices),
+    };
+    let non_manifold_edges = topology.edges.iter()
+        .filter(|edge| edge.is_non_manifold())
+        .map(|edge| edge_issue(edge.vertices, &edge.faces))
+        .collect();
+    let inconsistent_edges = topology.edges.iter()
+        .filter(|edge| edge.faces.len() == 2)
+        .filter(|edge| {
+            topology.directed_edge(edge.faces[0], edge.vertices) == topology.directed_edge(edge.faces[1], edge.vertices)
+        })
+        .map(|edge| edge_issue(edge.vertices, &edge.faces))
+        .collect();
+
+    let degenerate_triangles = (0..mesh.num_triangles())
+        .filter(|&face| mesh.is_degenerate(face))
+        .map(|face| FaceIssue { faces: vec![face as u32], location: face_centre(face as u32) })
+        .collect();
+
+    let mut faces_by_vertices: HashMap<[u32; 3], Vec<u32>> = HashMap::new();
+    for (face, tri) in mesh.triangles.iter().enumerate() {
+        let mut key = *tri;
+        key.sort_unstable();
+        faces_by_vertices.entry(key).or_default().push(face as u32);
+    }
+    let mut duplicate_faces: Vec<FaceIssue> = faces_by_vertices.into_values()
+        .filter(|faces| faces.len() > 1)
+        .map(|faces| FaceIssue { location: face_centre(faces[0]), faces })
+        .collect();
+    duplicate_faces.sort_by_key(|issue| issue.faces[0]);
+
+    let (self_intersections, coplanar_overlaps) = self_intersections(mesh);
+    ValidationReport {
+        boundary_loops: boundary_loops(mesh, &topology),
+        non_manifold_edges,
+        non_manifold_vertices: non_manifold_vertices(mesh, &topology),
+        inconsistent_edges,
+        degenerate_triangles,
+        duplicate_faces,
+        self_intersections,
+        coplanar_overlaps,
+    }
+}
+
+// Welds each mesh of a scene first, so indices refer to the welded meshes
+pub fn validate_scene(file: &str, scene: &Scene, weld_epsilon: f32) -> FileValidation {
+    let meshes = scene.meshes.iter()
+        .map(|mesh| MeshValidation {
+            name: mesh.name.clone(),
+            report: validate(&weld(mesh, weld_epsilon)),
+        })
+        .collect();
+    FileValidation { file: file.to_string(), meshes }
+}
+
+fn midpoint(points: &[[f32; 3]]) -> [f32; 3] {
+    [0, 1, 2].map(|i| points.iter().map(|point| point[i]).sum::<f32>() / points.len() as f32)
+}
+
+// Chains the directed boundary edges into loops, each edge used once
+pub fn boundary_loops(mesh: &TriMesh, topology: &Topology) -> Vec<BoundaryLoop> {
+    let edges = topology.boundary_edges();
+    let mut outgoing: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, edge) in edges.iter().enumerate() {
+        outgoing.entry(edge[0]).or_default().push(index);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut loops = Vec::new();
+    for start in 0..edges.len() {
+        if used[start] {
+            continue;
+        }
+        let mut vertices = vec![edges[start][0]];
+        let mut current = start;
+        let closed = loop {
+            used[current] = true;
+            let next_vertex = edges[current][1];
+            if next_vertex == vertices[0] {
+                break true;
+            }
+            vertices.push(next_vertex);
+            let next = outgoing.get(&next_vertex)
+                .and_then(|candidates| candidates.iter().copied().find(|&candidate| !used[candidate]));
+            match next {
+                Some(next) => current = next,
+                None => break false,
+            }
+        };
+
+        let points: Vec<[f32; 3]> = vertices.iter().map(|&vertex| mesh.positions[vertex as usize]).collect();
+        let segments = if closed { points.len() } else { points.len() - 1 };
+        let length = (0..segments)
+            .map(|i| distance(points[i], points[(i + 1) % points.len()]))
+            .sum();
+        loops.push(BoundaryLoop { vertices, length, closed, location: midpoint(&points) });
+    }
+    loops
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f64 {
+    (Vector3::from(a).cast::<f64>().unwrap() - Vector3::from(b).cast::<f64>().unwrap()).magnitude()
+}
+
+// A vertex is manifold when the faces around it form a single fan,
+// connected through the edges that meet at the vertex
+fn non_manifold_vertices(mesh: &TriMesh, topology: &Topology) -> Vec<VertexIssue> {
+    let mut issues = Vec::new();
+    let mut fan_of: Vec<usize> = Vec::new();
+    for (vertex, faces) in topology.vertex_faces.iter().enumerate() {
+        if faces.len() < 2 {
+            continue;
+        }
+
+        // Union-find over the faces around this vertex, joined by shared edges
+        fan_of.clear();
+        fan_of.extend(0..faces.len());
+        let mut first_face_of_edge: HashMap<u32, usize> = HashMap::new();
+        for (local, &face) in faces.iter().enumerate() {
+            for &edge in &topology.face_edges[face as usize] {
+                if !topology.edges[edge as usize].vertices.contains(&(vertex as u32)) {
+                    continue;
+                }
+                let other = *first_face_of_edge.entry(edge).or_insert(local);
+                let (a, b) = (root(&mut fan_of, local), root(&mut fan_of, other));
+                fan_of[a] = b;
+            }
+        }
+
+        let fans = (0..faces.len()).filter(|&local| root(&mut fan_of, local) == local).count();
+        if fans > 1 {
+            issues.push(VertexIssue { vertex: vertex as u32, fans, location: mesh.positions[vertex] });
+        }
+    }
+    issues
+}
+
+fn root(fan_of: &mut [usize], mut index: usize) -> usize {
+    while fan_of[index] != index {
+        fan_of[index] = fan_of[fan_of[index]];
+        index = fan_of[index];
+    }
+    index
+}
+
+// Broad phase on bounding boxes, then pairs of faces in the same plane go
+// through an exact overlap test and the rest through an edge against
+// triangle test in f64 with a small tolerance. Crossing pairs come first,
+// coplanar overlaps second.
+fn self_intersections(mesh: &TriMesh) -> (Vec<FaceIssue>, Vec<FaceIssue>) {
+    let mut issues = Vec::new();
+    let mut overlaps = Vec::new();
+    for [a, b] in overlapping_pairs(mesh) {
+        // Neighbours across an edge can't pass through each other without
+        // also being inconsistently wound or folded, which is reported
+        // elsewhere. Faces meeting at a corner are tested away from it.
+        let (tri_a, tri_b) = (mesh.triangles[a as usize], mesh.triangles[b as usize]);
+        let shared: Vec<u32> = tri_a.iter().copied().filter(|vertex| tri_b.contains(vertex)).collect();
+        if shared.len() > 1 {
+            continue;
+        }
+        if let Some(point) = coplanar_overlap(mesh.triangle(a as usize), mesh.triangle(b as usize)) {
+            overlaps.push(FaceIssue { faces: vec![a, b], location: point });
+            continue;
+        }
+        let corner = |tri: [u32; 3]| shared.first().and_then(|vertex| tri.iter().position(|other| other == vertex));
+        let intersection = triangles_intersect(
+            mesh.triangle(a as usize),
+            mesh.triangle(b as usize),
+            [corner(tri_a), corner(tri_b)],
+        );
+        if let Some(point) = intersection {
+            issues.push(FaceIssue { faces: vec![a, b], location: point });
+        }
+    }
+    issues.sort_by_key(|issue| (issue.faces[0], issue.faces[1]));
+    overlaps.sort_by_key(|issue| (issue.faces[0], issue.faces[1]));
+    (issues, overlaps)
+}
+
+// Where two triangles in exactly the same plane overlap, if they do. Seen
+// in the plane, they don't when some edge of one has all of the other on
+// or beyond it, otherwise their interiors share some area. Degenerate
+// triangles are reported on their own and left out here.
+fn coplanar_overlap(a: [[f32; 3]; 3], b: [[f32; 3]; 3]) -> Option<[f32; 3]> {
+    // The exact predicates need finite input
+    if ![a, b].iter().flatten().flatten().all(|v| v.is_finite()) {
+        return None;
+    }
+    if !b.iter().all(|&point| side_of_plane(a, point).is_eq()) {
+        return None;
+    }
+    let axes = projection_axes(facet_normal(a[0], a[1], a[2]).map(|v| v as f64));
+    let winding = |[p, q, r]: [[f32; 3]; 3]| orient2d_f32(p, q, r, axes);
+    let (winding_a, winding_b) = (winding(a), winding(b));
+    if winding_a.is_eq() || winding_b.is_eq() {
+        return None;
+    }
+    let separates = |tri: [[f32; 3]; 3], inward, other: [[f32; 3]; 3]| (0..3).any(|i| {
+        other.iter().all(|&point| orient2d_f32(tri[i], tri[(i + 1) % 3], point, axes) != inward)
+    });
+    if separates(a, winding_a, b) || separates(b, winding_b, a) {
+        return None;
+    }
+    Some(midpoint(&[a, b].concat()))
+}
+
+// Where an edge of either triangle passes through the interior of the
+// other. Triangles that only touch at shared vertices or edges aren't
+// reported, and coplanar ones are tested before getting here. Edges
+// touching a shared corner of either triangle are skipped.
+fn triangles_intersect(a: [[f32; 3]; 3], b: [[f32; 3]; 3], shared: [Option<usize>; 2]) -> Option<[f32; 3]> {
+    let a = a.map(|v| Vector3::from(v).cast::<f64>().unwrap());
+    let b = b.map(|v| Vector3::from(v).cast::<f64>().unwrap());
+    let edges = |corner: Option<usize>| (0..3).filter(move |&i| corner != Some(i) && corner != Some((i + 1) % 3));
+    edges(shared[0]).find_map(|i| segment_hits_triangle(a[i], a[(i + 1) % 3], b))
+        .or_else(|| edges(shared[1]).find_map(|i| segment_hits_triangle(b[i], b[(i + 1) % 3], a)))
+        .map(|point| point.cast::<f32>().unwrap().into())
+}
+
+// Möller-Trumbore restricted to the open segment and the triangle's interior
+fn segment_hits_triangle(start: Vector3<f64>, end: Vector3<f64>, [a, b, c]: [Vector3<f64>; 3]) -> Option<Vector3<f64>> {
+    let direction = end - start;
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    let scale = direction.magnitude() * ab.magnitude() * ac.magnitude();
+    if determinant.abs() <= INTERSECTION_EPSILON * scale {
+        return None;
+    }
+
+    let offset = start - a;
+    let u = offset.dot(p) / determinant;
+    let q = offset.cross(ab);
+    let v = direction.dot(q) / determinant;
+    let t = ac.dot(q) / determinant;
+    let inside = u > INTERSECTION_EPSILON && v > INTERSECTION_EPSILON && u + v < 1.0 - INTERSECTION_EPSILON;
+    let on_segment = t > INTERSECTION_EPSILON && t < 1.0 - INTERSECTION_EPSILON;
+    (inside && on_segment).then(|| start + direction * t)
+}
+
+fn list<T>(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    issues: &[T],
+    describe: impl Fn(&T) -> String,
+) -> fmt::Result {
+    writeln!(f, "  {}: {}", title, issues.len())?;
+    for issue in issues.iter().take(MAX_LISTED) {
+        writeln!(f, "    {}", describe(issue))?;
+    }
+    if issues.len() > MAX_LISTED {
+        writeln!(f, "    ... and {} more", issues.len() - MAX_LISTED)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  {}", if self.is_watertight() { "watertight" } else { "not watertight" })?;
+        list(f, "boundary loops", &self.boundary_loops, |hole| format!(
+            "{} edges, length {:.3}, at {:?}{}",
+            if hole.closed { hole.vertices.len() } else { hole.vertices.len() - 1 },
+            hole.length,
+            hole.location,
+            if hole.closed { "" } else { " (unclosed)" },
+        ))?;
+        list(f, "non-manifold edges", &self.non_manifold_edges, |edge| format!(
+            "vertices {:?} shared by {} faces, at {:?}", edge.vertices, edge.faces.len(), edge.location))?;
+        list(f, "non-manifold vertices", &self.non_manifold_vertices, |vertex| format!(
+            "vertex {} joins {} fans, at {:?}", vertex.vertex, vertex.fans, vertex.location))?;
+        list(f, "inconsistently wound edges", &self.inconsistent_edges, |edge| format!(
+            "faces {:?} at {:?}", edge.faces, edge.location))?;
+        list(f, "degenerate triangles", &self.degenerate_triangles, |face| format!(
+            "face {} at {:?}", face.faces[0], face.location))?;
+        list(f, "duplicate faces", &self.duplicate_faces, |face| format!(
+            "faces {:?} at {:?}", face.faces, face.location))?;
+        list(f, "self-intersections", &self.self_intersections, |pair| format!(
+            "faces {:?} at {:?}", pair.faces, pair.location))?;
+        list(f, "coplanar overlaps", &self.coplanar_overlaps, |pair| format!(
+            "faces {:?} at {:?}", pair.faces, pair.location))
+    }
+}
+
+impl fmt::Display for FileValidation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.file)?;
+        for mesh in &self.meshes {
+            writeln!(f, "{}", mesh.name)?;
+            write!(f, "{}", mesh.report)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+
+    // The cube with one more, separate, triangle added
+    fn cube_with(triangle: [[f32; 3]; 3]) -> TriMesh {
+        let mut mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        let first = mesh.positions.len() as u32;
+        mesh.positions.extend(triangle);
+        mesh.triangles.push([first, first + 1, first + 2]);
+        mesh
+    }
+
+    #[test]
+    fn closed_cube_has_no_problems() {
+        let report = validate(&cube("cube", [0.0; 3], [10.0; 3]));
+        assert!(report.is_watertight());
+        assert_eq!(report.num_problems(), 0);
+    }
+
+    #[test]
+    fn finds_faces_crossing_each_other() {
+        let report = validate(&cube_with([[5.0, 2.0, -3.0], [5.0, 8.0, -3.0], [5.0, 5.0, 3.0]]));
+        assert!(!report.self_intersections.is_empty());
+        assert!(report.coplanar_overlaps.is_empty());
+    }
+
+    #[test]
+    fn finds_coplanar_overlaps() {
+        let report = validate(&cube_with([[2.0, 2.0, 0.0], [8.0, 2.0, 0.0], [2.0, 8.0, 0.0]]));
+        assert!(!report.coplanar_overlaps.is_empty());
+        assert!(report.self_intersections.is_empty());
+        for issue in &report.coplanar_overlaps {
+            assert!(issue.faces.contains(&12));
+            assert!(issue.faces[0] < 2, "only the bottom faces are in that plane");
+        }
+    }
+
+    #[test]
+    fn coplanar_faces_side_by_side_dont_overlap() {
+        // In the plane of the bottom face, touching it along a line
+        let report = validate(&cube_with([[10.0, 2.0, 0.0], [14.0, 2.0, 0.0], [10.0, 6.0, 0.0]]));
+        assert!(report.coplanar_overlaps.is_empty());
+        assert!(report.self_intersections.is_empty());
+    }
+}
@@ -6,6 +6,7 @@ pub struct Vertex {
     pub normal: [f32; 3]
 }
 
+#[allow(dead_code)]
 impl Vertex {
     const ATTRIBS: [wgpu::VertexAttribute; 3] = 
         wgpu::vertex_attr_array![
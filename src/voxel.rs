@@ -0,0 +1,555 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use cgmath::{InnerSpace, Vector3};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::mesh::{Scene, TriMesh};
+
+// Past this many voxels the grid alone runs into gigabytes
+const MAX_VOXELS: usize = 1 << 28;
+// Empty layers around the mesh, so the outside is connected all the way round
+const PADDING: usize = 2;
+
+const EMPTY: u8 = 0;
+const SOLID: u8 = 1;
+const OUTSIDE: u8 = 2;
+// The surface passes through, but which side the centre is on is open
+const SURFACE: u8 = 3;
+// Surface voxels with their centre outside
+const PEELED: u8 = 4;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VoxelReport {
+    pub voxel_size: f32,
+    pub resolution: [usize; 3],
+    // Voxels the outside couldn't reach and the surface doesn't pass
+    // through, the inside of the solid as well as any cavities
+    pub interior_voxels: usize,
+    pub interior_volume: f64,
+    // How far the new surface's vertices are from the original surface
+    pub max_error: f64,
+    pub mean_error: f64,
+}
+
+#[derive(Debug)]
+pub enum VoxelError {
+    InvalidSize(f32),
+    TooFine { voxel_size: f32, voxels: usize },
+}
+
+struct Grid {
+    origin: Vector3<f64>,
+    size: f64,
+    dims: [usize; 3],
+}
+
+impl Grid {
+    fn index(&self, [i, j, k]: [usize; 3]) -> usize {
+        (k * self.dims[1] + j) * self.dims[0] + i
+    }
+
+    fn center(&self, voxel: [usize; 3]) -> Vector3<f64> {
+        self.origin + Vector3::new(voxel[0] as f64 + 0.5, voxel[1] as f64 + 0.5, voxel[2] as f64 + 0.5) * self.size
+    }
+
+    fn voxel_of(&self, point: Vector3<f64>) -> [usize; 3] {
+        [0, 1, 2].map(|i| (((point[i] - self.origin[i]) / self.size).floor().max(0.0) as usize).min(self.dims[i] - 1))
+    }
+
+    fn len(&self) -> usize {
+        self.dims.iter().product()
+    }
+}
+
+// Rebuilds a mesh as the outside surface of everything it encloses, on a
+// grid of voxels this size. Voxels the surface passes through block the
+// outside and whatever it can't reach is filled, so holes smaller than a
+// voxel and any mess inside don't matter. The new surface is dual contoured
+// with its vertices where the grid crosses the original surface, which
+// only loses detail finer than a voxel.
+pub fn voxel_fill(mesh: &TriMesh, voxel_size: f32) -> Result<(TriMesh, VoxelReport), VoxelError> {
+    if voxel_size.is_nan() || voxel_size <= 0.0 {
+        return Err(VoxelError::InvalidSize(voxel_size));
+    }
+    let Some(bounds) = mesh.bounds() else {
+        return Ok((TriMesh::new(&mesh.name), VoxelReport { voxel_size, ..Default::default() }));
+    };
+
+    let size = voxel_size as f64;
+    let min = Vector3::from(bounds.min).cast::<f64>().unwrap();
+    let extent = Vector3::from(bounds.size()).cast::<f64>().unwrap();
+    let dims = [0, 1, 2].map(|i| (extent[i] / size).floor() as usize + 1 + 2 * PADDING);
+    let voxels = dims.iter().try_fold(1usize, |total, &n| total.checked_mul(n)).unwrap_or(usize::MAX);
+    if voxels > MAX_VOXELS {
+        return Err(VoxelError::TooFine { voxel_size, voxels });
+    }
+    let grid = Grid { origin: min - Vector3::new(1.0, 1.0, 1.0) * (PADDING as f64 * size), size, dims };
+
+    let faces_in = rasterize(mesh, &grid);
+    let mut state = vec![EMPTY; grid.len()];
+    for &(voxel, _) in &faces_in {
+        state[voxel] = SURFACE;
+    }
+    flood_outside(&grid, &mut state);
+    let interior_voxels = state.iter().filter(|&&voxel| voxel == EMPTY).count();
+    peel(mesh, &grid, &mut state, &faces_in);
+    for voxel in state.iter_mut() {
+        *voxel = match *voxel {
+            PEELED => OUTSIDE,
+            OUTSIDE => OUTSIDE,
+            _ => SOLID,
+        };
+    }
+    // Filling ambiguous cells can close off a pocket of outside, which
+    // would come out as a cavity
+    while resolve_ambiguities(&grid, &mut state) {
+        for voxel in state.iter_mut().filter(|voxel| **voxel == OUTSIDE) {
+            *voxel = EMPTY;
+        }
+        flood_outside(&grid, &mut state);
+        for voxel in state.iter_mut().filter(|voxel| **voxel == EMPTY) {
+            *voxel = SOLID;
+        }
+    }
+
+    let (solid, vertex_cells) = contour(mesh, &grid, &state, &faces_in);
+    let errors: Vec<f64> = solid.positions.par_iter()
+        .zip(&vertex_cells)
+        .filter_map(|(&position, &cell)| surface_distance(mesh, &grid, &faces_in, cell, Vector3::from(position).cast().unwrap()))
+        .collect();
+
+    let report = VoxelReport {
+        voxel_size,
+        resolution: dims.map(|n| n - 2 * PADDING),
+        interior_voxels,
+        interior_volume: interior_voxels as f64 * size * size * size,
+        max_error: errors.iter().copied().fold(0.0, f64::max),
+        mean_error: if errors.is_empty() { 0.0 } else { errors.iter().sum::<f64>() / errors.len() as f64 },
+    };
+    Ok((solid, report))
+}
+
+// Voxel fills every mesh in a scene, one report per mesh
+pub fn voxel_fill_scene(scene: &Scene, voxel_size: f32) -> Result<(Scene, Vec<VoxelReport>), VoxelError> {
+    let (meshes, reports) = scene.meshes.iter()
+        .map(|mesh| voxel_fill(mesh, voxel_size))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    Ok((Scene { header: scene.header.clone(), meshes }, reports))
+}
+
+// Every voxel each face passes through, sorted by voxel
+fn rasterize(mesh: &TriMesh, grid: &Grid) -> Vec<(usize, u32)> {
+    let mut faces_in: Vec<(usize, u32)> = (0..mesh.num_triangles())
+        .into_par_iter()
+        .flat_map_iter(|face| {
+            let corners = mesh.triangle(face).map(|corner| Vector3::from(corner).cast::<f64>().unwrap());
+            let low = grid.voxel_of(Vector3::new(
+                corners[0].x.min(corners[1].x).min(corners[2].x),
+                corners[0].y.min(corners[1].y).min(corners[2].y),
+                corners[0].z.min(corners[1].z).min(corners[2].z),
+            ));
+            let high = grid.voxel_of(Vector3::new(
+                corners[0].x.max(corners[1].x).max(corners[2].x),
+                corners[0].y.max(corners[1].y).max(corners[2].y),
+                corners[0].z.max(corners[1].z).max(corners[2].z),
+            ));
+            let mut touched = Vec::new();
+            for k in low[2]..=high[2] {
+                for j in low[1]..=high[1] {
+                    for i in low[0]..=high[0] {
+                        if triangle_overlaps_box(corners, grid.center([i, j, k]), grid.size / 2.0) {
+                            touched.push((grid.index([i, j, k]), face as u32));
+                        }
+                    }
+                }
+            }
+            touched
+        })
+        .collect();
+    faces_in.par_sort_unstable();
+    faces_in
+}
+
+fn faces_in_voxel(faces_in: &[(usize, u32)], voxel: usize) -> impl Iterator<Item = u32> + '_ {
+    let start = faces_in.partition_point(|&(other, _)| other < voxel);
+    faces_in[start..].iter().take_while(move |&&(other, _)| other == voxel).map(|&(_, face)| face)
+}
+
+// Marks every empty voxel connected to the grid's corner through faces
+fn flood_outside(grid: &Grid, state: &mut [u8]) {
+    let mut queue = VecDeque::from([[0, 0, 0]]);
+    state[0] = OUTSIDE;
+    while let Some(voxel) = queue.pop_front() {
+        for axis in 0..3 {
+            for step in [-1isize, 1] {
+                let Some(next) = neighbour(grid, voxel, axis, step) else {
+                    continue;
+                };
+                let index = grid.index(next);
+                if state[index] == EMPTY {
+                    state[index] = OUTSIDE;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+}
+
+// Surface voxels whose centre can be reached from an outside neighbour's
+// without crossing the surface. Only voxels next to the outside the flood
+// found are peeled, so a hole in the mesh can't let the outside in deeper
+// than that.
+fn peel(mesh: &TriMesh, grid: &Grid, state: &mut [u8], faces_in: &[(usize, u32)]) {
+    let mut candidates: Vec<[usize; 3]> = faces_in.iter()
+        .map(|&(voxel, _)| voxel)
+        .filter(|&voxel| state[voxel] == SURFACE)
+        .map(|voxel| [voxel % grid.dims[0], voxel / grid.dims[0] % grid.dims[1], voxel / (grid.dims[0] * grid.dims[1])])
+        .filter(|&voxel| neighbours(grid, voxel).any(|other| state[grid.index(other)] == OUTSIDE))
+        .collect();
+    candidates.dedup();
+
+    // Peeling one voxel can open the way to the next along the surface
+    loop {
+        let peeled: Vec<usize> = candidates.par_iter()
+            .filter(|&&voxel| state[grid.index(voxel)] == SURFACE)
+            .filter(|&&voxel| {
+                neighbours(grid, voxel)
+                    .filter(|&other| matches!(state[grid.index(other)], OUTSIDE | PEELED))
+                    .any(|other| hit(mesh, grid, faces_in, other, voxel).is_none())
+            })
+            .map(|&voxel| grid.index(voxel))
+            .collect();
+        if peeled.is_empty() {
+            break;
+        }
+        for voxel in peeled {
+            state[voxel] = PEELED;
+        }
+    }
+}
+
+fn neighbours(grid: &Grid, voxel: [usize; 3]) -> impl Iterator<Item = [usize; 3]> + '_ {
+    (0..3).flat_map(move |axis| [-1, 1].into_iter().filter_map(move |step| neighbour(grid, voxel, axis, step)))
+}
+
+fn neighbour(grid: &Grid, mut voxel: [usize; 3], axis: usize, step: isize) -> Option<[usize; 3]> {
+    let moved = voxel[axis].checked_add_signed(step)?;
+    if moved >= grid.dims[axis] {
+        return None;
+    }
+    voxel[axis] = moved;
+    Some(voxel)
+}
+
+// Corner c of a cell is the voxel offset by its bits, x first
+fn cell_corner([i, j, k]: [usize; 3], corner: usize) -> [usize; 3] {
+    [i + (corner & 1), j + ((corner >> 1) & 1), k + ((corner >> 2) & 1)]
+}
+
+fn cell_mask(grid: &Grid, state: &[u8], cell: [usize; 3]) -> usize {
+    (0..8).filter(|&corner| state[grid.index(cell_corner(cell, corner))] == SOLID).fold(0, |mask, corner| mask | 1 << corner)
+}
+
+// A cell's surface is a single disc when its solid corners and its empty
+// corners each hang together along its edges, and no face has them in a
+// checkerboard. Anything else would make the contoured surface pinch.
+fn unambiguous(mask: usize) -> bool {
+    let connected = |set: usize| {
+        let Some(first) = (0..8).find(|&corner| set & 1 << corner != 0) else {
+            return true;
+        };
+        let mut reached = 1 << first;
+        let mut stack = vec![first];
+        while let Some(corner) = stack.pop() {
+            for bit in [1, 2, 4] {
+                let next = corner ^ bit;
+                if set & 1 << next != 0 && reached & 1 << next == 0 {
+                    reached |= 1 << next;
+                    stack.push(next);
+                }
+            }
+        }
+        reached == set
+    };
+    let checkerboard = (0..3).any(|axis| {
+        let [u, v] = [1 << ((axis + 1) % 3), 1 << ((axis + 2) % 3)];
+        [0, 1 << axis].iter().any(|&side| {
+            let solid = |corner: usize| mask & 1 << corner != 0;
+            let (a, b, c, d) = (solid(side), solid(side | u), solid(side | u | v), solid(side | v));
+            a == c && b == d && a != b
+        })
+    });
+    connected(mask) && connected(!mask & 0xff) && !checkerboard
+}
+
+// Fills every cell whose surface would pinch, until none are left. Cells
+// touching the grid's outer layer are never ambiguous since it stays empty.
+fn resolve_ambiguities(grid: &Grid, state: &mut [u8]) -> bool {
+    let table: Vec<bool> = (0..256).map(unambiguous).collect();
+    let mut changed = false;
+    loop {
+        let mut filled = false;
+        for k in 1..grid.dims[2] - 2 {
+            for j in 1..grid.dims[1] - 2 {
+                for i in 1..grid.dims[0] - 2 {
+                    if table[cell_mask(grid, state, [i, j, k])] {
+                        continue;
+                    }
+                    for corner in 0..8 {
+                        state[grid.index(cell_corner([i, j, k], corner))] = SOLID;
+                    }
+                    filled = true;
+                }
+            }
+        }
+        if !filled {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+// Surface nets: a vertex in every cell the surface passes through, at the
+// average of where it crosses the cell's edges, and a quad across every
+// edge between a solid and an empty voxel. Also returns each vertex's cell.
+fn contour(mesh: &TriMesh, grid: &Grid, state: &[u8], faces_in: &[(usize, u32)]) -> (TriMesh, Vec<[usize; 3]>) {
+    let mut solid = TriMesh::new(&mesh.name);
+    let mut cells = Vec::new();
+    let mut vertex_of: HashMap<[usize; 3], u32> = HashMap::new();
+    let is_solid = |voxel: [usize; 3]| state[grid.index(voxel)] == SOLID;
+
+    let mut vertex = |cell: [usize; 3], solid: &mut TriMesh| {
+        *vertex_of.entry(cell).or_insert_with(|| {
+            let mut total = Vector3::new(0.0, 0.0, 0.0);
+            let mut count = 0.0;
+            for corner in 0..8 {
+                for bit in [1, 2, 4] {
+                    let other = corner | bit;
+                    let (a, b) = (cell_corner(cell, corner), cell_corner(cell, other));
+                    if other == corner || is_solid(a) == is_solid(b) {
+                        continue;
+                    }
+                    let (inside, outside) = if is_solid(a) { (a, b) } else { (b, a) };
+                    total += crossing(mesh, grid, faces_in, inside, outside);
+                    count += 1.0;
+                }
+            }
+            let position = total / count;
+            solid.positions.push([position.x as f32, position.y as f32, position.z as f32]);
+            cells.push(cell);
+            solid.positions.len() as u32 - 1
+        })
+    };
+
+    for k in 0..grid.dims[2] {
+        for j in 0..grid.dims[1] {
+            for i in 0..grid.dims[0] {
+                let voxel = [i, j, k];
+                for axis in 0..3 {
+                    // Solid voxels are never in the outer layer, so the cells
+                    // around an edge between solid and empty are in the grid
+                    let Some(next) = neighbour(grid, voxel, axis, 1) else {
+                        continue;
+                    };
+                    if is_solid(voxel) == is_solid(next) {
+                        continue;
+                    }
+                    // The four cells around the edge, counterclockwise seen
+                    // from past its far end
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    let around = [(1, 1), (0, 1), (0, 0), (1, 0)].map(|(du, dv)| {
+                        let mut cell = voxel;
+                        cell[u] -= du;
+                        cell[v] -= dv;
+                        vertex(cell, &mut solid)
+                    });
+                    let [a, b, c, d] = if is_solid(voxel) { around } else { [around[3], around[2], around[1], around[0]] };
+                    // Split along the shorter diagonal, so the quad folds the least
+                    let length = |x: u32, y: u32| (Vector3::from(solid.positions[x as usize]) - Vector3::from(solid.positions[y as usize])).magnitude2();
+                    if length(a, c) <= length(b, d) {
+                        solid.triangles.extend([[a, b, c], [a, c, d]]);
+                    } else {
+                        solid.triangles.extend([[a, b, d], [b, c, d]]);
+                    }
+                }
+            }
+        }
+    }
+    (solid, cells)
+}
+
+// Where the original surface crosses the way from an outside voxel's
+// centre to a solid neighbour's, nearest the outside one. Halfway if it
+// doesn't, which happens where the voxel was filled or the mesh has a hole.
+fn crossing(mesh: &TriMesh, grid: &Grid, faces_in: &[(usize, u32)], inside: [usize; 3], outside: [usize; 3]) -> Vector3<f64> {
+    let (from, to) = (grid.center(outside), grid.center(inside));
+    from + (to - from) * hit(mesh, grid, faces_in, outside, inside).unwrap_or(0.5)
+}
+
+// How far from one voxel's centre to the next the first face passing
+// through either of them is, if any
+fn hit(mesh: &TriMesh, grid: &Grid, faces_in: &[(usize, u32)], from: [usize; 3], to: [usize; 3]) -> Option<f64> {
+    let (start, end) = (grid.center(from), grid.center(to));
+    faces_in_voxel(faces_in, grid.index(from))
+        .chain(faces_in_voxel(faces_in, grid.index(to)))
+        .filter_map(|face| segment_hits(mesh.triangle(face as usize).map(|corner| Vector3::from(corner).cast().unwrap()), start, end))
+        .fold(None, |nearest: Option<f64>, t| Some(nearest.map_or(t, |nearest| nearest.min(t))))
+}
+
+// How far along the segment it passes through the triangle, if it does
+// (Möller and Trumbore)
+fn segment_hits([a, b, c]: [Vector3<f64>; 3], from: Vector3<f64>, to: Vector3<f64>) -> Option<f64> {
+    let direction = to - from;
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < f64::EPSILON * ab.magnitude2().max(ac.magnitude2()) * direction.magnitude() {
+        return None;
+    }
+    let offset = from - a;
+    let u = offset.dot(p) / determinant;
+    let q = offset.cross(ab);
+    let v = direction.dot(q) / determinant;
+    let t = ac.dot(q) / determinant;
+    (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && (0.0..=1.0).contains(&t)).then_some(t)
+}
+
+// Separating axis test between a triangle and a cube (Akenine-Möller). The
+// cube is grown a touch so faces running exactly along voxel boundaries
+// mark the voxels either side.
+fn triangle_overlaps_box(corners: [Vector3<f64>; 3], center: Vector3<f64>, half: f64) -> bool {
+    let half = half * (1.0 + 1e-9);
+    let v = corners.map(|corner| corner - center);
+    let separated = |axis: Vector3<f64>| {
+        let projections = v.map(|point| point.dot(axis));
+        let radius = half * (axis.x.abs() + axis.y.abs() + axis.z.abs());
+        let (low, high) = (projections[0].min(projections[1]).min(projections[2]), projections[0].max(projections[1]).max(projections[2]));
+        low > radius || high < -radius
+    };
+
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+    if axes.iter().any(|&axis| separated(axis)) || separated(edges[0].cross(edges[1])) {
+        return false;
+    }
+    !edges.iter().any(|&edge| axes.iter().any(|&axis| separated(axis.cross(edge))))
+}
+
+// Distance from a new vertex to the nearest original face passing through
+// the voxels around its cell
+fn surface_distance(mesh: &TriMesh, grid: &Grid, faces_in: &[(usize, u32)], cell: [usize; 3], point: Vector3<f64>) -> Option<f64> {
+    let mut nearest: Option<f64> = None;
+    for k in cell[2].saturating_sub(1)..(cell[2] + 3).min(grid.dims[2]) {
+        for j in cell[1].saturating_sub(1)..(cell[1] + 3).min(grid.dims[1]) {
+            for i in cell[0].saturating_sub(1)..(cell[0] + 3).min(grid.dims[0]) {
+                for face in faces_in_voxel(faces_in, grid.index([i, j, k])) {
+                    let corners = mesh.triangle(face as usize).map(|corner| Vector3::from(corner).cast().unwrap());
+                    let distance = (closest_point(corners, point) - point).magnitude();
+                    nearest = Some(nearest.map_or(distance, |nearest| nearest.min(distance)));
+                }
+            }
+        }
+    }
+    nearest
+}
+
+// Closest point on a triangle, by which region of it the point falls in
+// (Ericson, Real-Time Collision Detection 5.1.5)
+fn closest_point([a, b, c]: [Vector3<f64>; 3], p: Vector3<f64>) -> Vector3<f64> {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = va + vb + vc;
+    if denominator == 0.0 {
+        // Degenerate triangle, the corners are as good as it gets
+        return [a, b, c].into_iter()
+            .min_by(|x, y| (x - p).magnitude2().total_cmp(&(y - p).magnitude2()))
+            .unwrap();
+    }
+    a + ab * (vb / denominator) + ac * (vc / denominator)
+}
+
+impl fmt::Display for VoxelReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [x, y, z] = self.resolution;
+        writeln!(
+            f,
+            "voxelized at {} ({}x{}x{}), {} voxels inside the surface ({:.3} of volume), surface within {:.4} of the original ({:.4} on average)",
+            self.voxel_size, x, y, z, self.interior_voxels, self.interior_volume, self.max_error, self.mean_error
+        )
+    }
+}
+
+impl fmt::Display for VoxelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxelError::InvalidSize(size) => write!(f, "voxel size {} is not a positive number", size),
+            VoxelError::TooFine { voxel_size, voxels } => {
+                write!(f, "voxel size {} needs {} voxels, more than the {} allowed", voxel_size, voxels, MAX_VOXELS)
+            },
+        }
+    }
+}
+
+impl std::error::Error for VoxelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::cube;
+    use crate::shell::extract_shells;
+    use crate::topology::Topology;
+
+    #[test]
+    fn fills_a_hollow_cube() {
+        let mut hollow = cube("hollow", [0.0; 3], [10.0; 3]);
+        hollow.append(&cube("hollow", [3.0; 3], [7.0; 3]));
+        let (solid, report) = voxel_fill(&hollow, 0.5).unwrap();
+
+        assert_eq!(solid.name, "hollow");
+        assert_eq!(extract_shells(&solid).len(), 1);
+        assert!(Topology::new(&solid).boundary_edges().is_empty());
+        // Corners come out bevelled, by less than a voxel
+        assert!((solid.signed_volume() - 1000.0).abs() < 10.0, "volume {}", solid.signed_volume());
+        assert!(report.max_error < 0.25, "surface off by {}", report.max_error);
+        // Both the walls and the hollow, less the voxels the surfaces pass through
+        assert!(report.interior_volume > 800.0 && report.interior_volume < 1000.0, "interior {}", report.interior_volume);
+    }
+
+    #[test]
+    fn refuses_bad_voxel_sizes() {
+        let mesh = cube("cube", [0.0; 3], [10.0; 3]);
+        for size in [0.0, -1.0, f32::NAN] {
+            assert!(matches!(voxel_fill(&mesh, size), Err(VoxelError::InvalidSize(_))));
+        }
+        assert!(matches!(voxel_fill(&mesh, 1e-4), Err(VoxelError::TooFine { .. })));
+    }
+}
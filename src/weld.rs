@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use crate::mesh::TriMesh;
+
+// Merges coincident vertices of a triangle soup into a compact indexed mesh.
+// An epsilon of 0.0 only merges bit-identical positions, anything larger
+// merges vertices that are within epsilon of an already welded vertex.
+// Triangles that collapse onto fewer than three vertices are dropped.
+// Normals are dropped too, a welded vertex keeps the first vertex's color.
+pub fn weld(mesh: &TriMesh, epsilon: f32) -> TriMesh {
+    let remap = if epsilon > 0.0 {
+        weld_within(&mesh.positions, epsilon)
+    } else {
+        weld_exact(&mesh.positions)
+    };
+
+    let mut welded = TriMesh::new(&mesh.name);
+    let mut colors = mesh.colors.as_ref().map(|_| Vec::new());
+    let mut face_colors = mesh.face_colors.as_ref().map(|_| Vec::new());
+    let mut compact: Vec<Option<u32>> = vec![None; mesh.positions.len()];
+    for (face, tri) in mesh.triangles.iter().enumerate() {
+        let tri = tri.map(|index| remap[index as usize]);
+        if tri[0] == tri[1] || tri[1] == tri[2] || tri[2] == tri[0] {
+            continue;
+        }
+
+        // Only keep vertices that are still referenced by a triangle
+        let tri = tri.map(|index| {
+            *compact[index as usize].get_or_insert_with(|| {
+                welded.positions.push(mesh.positions[index as usize]);
+                if let (Some(colors), Some(source)) = (colors.as_mut(), mesh.colors.as_ref()) {
+                    colors.push(source[index as usize]);
+                }
+                welded.positions.len() as u32 - 1
+            })
+        });
+        welded.triangles.push(tri);
+        if let (Some(face_colors), Some(source)) = (face_colors.as_mut(), mesh.face_colors.as_ref()) {
+            face_colors.push(source[face]);
+        }
+    }
+
+    welded.colors = colors;
+    welded.face_colors = face_colors;
+    welded
+}
+
+// Maps every vertex to the first vertex with exactly the same position
+fn weld_exact(positions: &[[f32; 3]]) -> Vec<u32> {
+    let mut seen: HashMap<[u32; 3], u32> = HashMap::with_capacity(positions.len());
+    positions.iter()
+        .enumerate()
+        .map(|(index, position)| {
+            // -0.0 and 0.0 are the same point but not the same bits
+            let key = position.map(|v| (v + 0.0).to_bits());
+            *seen.entry(key).or_insert(index as u32)
+        })
+        .collect()
+}
+
+// Spatial hash with cells epsilon wide, so any vertex within epsilon of
+// another is in the same or one of the 26 neighbouring cells
+fn weld_within(positions: &[[f32; 3]], epsilon: f32) -> Vec<u32> {
+    let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::with_capacity(positions.len());
+    let epsilon2 = epsilon * epsilon;
+    let cell_of = |position: &[f32; 3]| position.map(|v| (v / epsilon).floor() as i64);
+
+    positions.iter()
+        .enumerate()
+        .map(|(index, position)| {
+            let cell = cell_of(position);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                        let Some(candidates) = grid.get(&neighbour) else {
+                            continue;
+                        };
+                        for &candidate in candidates {
+                            if distance2(&positions[candidate as usize], position) <= epsilon2 {
+                                return candidate;
+                            }
+                        }
+                    }
+                }
+            }
+
+            grid.entry(cell).or_default().push(index as u32);
+            index as u32
+        })
+        .collect()
+}
+
+fn distance2(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two triangles sharing an edge, written out as an unindexed soup with
+    // the second triangle's copy of the shared edge moved by the offset
+    fn soup(offset: [f32; 3]) -> TriMesh {
+        let moved = |[x, y, z]: [f32; 3]| [x + offset[0], y + offset[1], z + offset[2]];
+        let mut mesh = TriMesh::new("soup");
+        mesh.positions = vec![
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+            moved([1.0, 0.0, 0.0]), [1.0, 1.0, 0.0], moved([0.0, 1.0, 0.0]),
+        ];
+        mesh.triangles = vec![[0, 1, 2], [3, 4, 5]];
+        mesh
+    }
+
+    #[test]
+    fn merges_vertices_within_epsilon() {
+        let welded = weld(&soup([0.001, 0.0, 0.0]), 0.01);
+        assert_eq!(welded.positions.len(), 4);
+        assert_eq!(welded.triangles, vec![[0, 1, 2], [1, 3, 2]]);
+        // The first of the merged vertices decides where they end up
+        assert_eq!(welded.positions[1], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn keeps_vertices_outside_epsilon() {
+        let welded = weld(&soup([0.02, 0.0, 0.0]), 0.01);
+        assert_eq!(welded.positions.len(), 6);
+        assert_eq!(welded.num_triangles(), 2);
+    }
+
+    #[test]
+    fn merges_across_cell_boundaries() {
+        // 0.999 and 1.001 hash into neighbouring cells 0.01 wide
+        let mut mesh = soup([0.0; 3]);
+        mesh.positions[1] = [0.999, 0.0, 0.0];
+        mesh.positions[3] = [1.001, 0.0, 0.0];
+        let welded = weld(&mesh, 0.01);
+        assert_eq!(welded.positions.len(), 4);
+    }
+
+    #[test]
+    fn zero_epsilon_only_merges_identical_positions() {
+        assert_eq!(weld(&soup([0.0; 3]), 0.0).positions.len(), 4);
+        assert_eq!(weld(&soup([1e-6, 0.0, 0.0]), 0.0).positions.len(), 6);
+
+        let mut mesh = soup([0.0; 3]);
+        mesh.positions[0] = [-0.0, 0.0, -0.0];
+        mesh.positions[3] = [1.0, -0.0, 0.0];
+        assert_eq!(weld(&mesh, 0.0).positions.len(), 4);
+    }
+
+    #[test]
+    fn drops_faces_that_collapse() {
+        let mut mesh = soup([0.0; 3]);
+        mesh.positions[4] = [1.0, 0.005, 0.0];
+        mesh.face_colors = Some(vec![Some([1.0, 0.0, 0.0]), Some([0.0, 1.0, 0.0])]);
+        let welded = weld(&mesh, 0.01);
+        assert_eq!(welded.triangles, vec![[0, 1, 2]]);
+        assert_eq!(welded.positions.len(), 3);
+        assert_eq!(welded.face_colors, Some(vec![Some([1.0, 0.0, 0.0])]));
+    }
+}
@@ -1,24 +1,26 @@
+use std::path::PathBuf;
+
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder
 };
 
+use crate::mesh::Scene;
 use crate::surface::State;
 
-pub async fn run() {
-    env_logger::init();
+pub async fn run(scene: Scene, save_path: PathBuf) {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
     window.set_title("Solidify");
 
-    let mut state = State::new(window).await;
+    let mut state = State::new(window, scene, save_path).await;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == state.window().id() => if !state.input(event) {
+        } if window_id == state.window().id() && !state.input(event) => {
             match event {
                 WindowEvent::CloseRequested
                 | WindowEvent::KeyboardInput {
@@ -43,7 +45,7 @@ pub async fn run() {
             state.update();
             match state.render() {
                 Ok(_) => {},
-                Err(wgpu::SurfaceError::Lost) => state.resize(state.size().clone()),
+                Err(wgpu::SurfaceError::Lost) => state.resize(*state.size()),
                 Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 Err(e) => eprintln!("{:?}", e),
             }